@@ -0,0 +1,10 @@
+use std::io::Read;
+
+/// Fuzz target for `cargo afl build --example fuzz`, which runs one input
+/// from stdin per process
+fn main() {
+    let mut input = Vec::new();
+    if std::io::stdin().read_to_end(&mut input).is_ok() {
+        chip_8::fuzz::run(&input);
+    }
+}
@@ -0,0 +1,293 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Context, Result};
+
+/// Programs are assembled to run from the usual load address
+const ORIGIN: u16 = 0x200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operand {
+    Register(u8),
+    I,
+    IndirectI,
+    DelayTimer,
+    SoundTimer,
+    Key,
+    Font,
+    Bcd,
+    Value(u16),
+}
+
+struct Line<'a> {
+    number: usize,
+    mnemonic: String,
+    operands: Vec<&'a str>,
+}
+
+/// Compiles assembly text to ROM bytes.
+///
+/// Uses the common CHIP-8 mnemonics (`LD Vx, byte`, `JP addr`,
+/// `DRW Vx, Vy, n`, ...), `label:` definitions and the `DB`/`DW` directives.
+/// Comments start with `;`.
+pub fn assemble(source: &str) -> Result<Vec<u8>> {
+    let mut labels = HashMap::new();
+    let mut lines = Vec::new();
+    let mut address = ORIGIN;
+
+    // First pass: collect labels and their addresses
+    for (index, text) in source.lines().enumerate() {
+        let number = index + 1;
+        let mut text = text.split(';').next().unwrap_or_default().trim();
+
+        if let Some((label, rest)) = text.split_once(':') {
+            let label = label.trim();
+            if label.is_empty() || !label.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                bail!("Line {}: invalid label {:?}", number, label);
+            }
+            // Registers and keywords take precedence over labels as operands,
+            // so a label with such a name could never be referenced
+            let reserved = parse_operand(label, &HashMap::new())
+                .is_ok_and(|operand| !matches!(operand, Operand::Value(_)));
+            if reserved {
+                bail!(
+                    "Line {}: {:?} is reserved and can't be a label",
+                    number,
+                    label
+                );
+            }
+            if labels.insert(label.to_ascii_lowercase(), address).is_some() {
+                bail!("Line {}: duplicate label {:?}", number, label);
+            }
+            text = rest.trim();
+        }
+
+        if text.is_empty() {
+            continue;
+        }
+
+        let (mnemonic, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+        let operands: Vec<&str> = rest
+            .split(',')
+            .map(str::trim)
+            .filter(|operand| !operand.is_empty())
+            .collect();
+        let mnemonic = mnemonic.to_ascii_uppercase();
+
+        let size = match mnemonic.as_str() {
+            "DB" => operands.len(),
+            "DW" => operands.len() * 2,
+            _ => 2,
+        };
+        address = u16::try_from(address as usize + size)
+            .ok()
+            .filter(|&address| address <= 0x1000)
+            .with_context(|| format!("Line {}: program does not fit in memory", number))?;
+
+        lines.push(Line {
+            number,
+            mnemonic,
+            operands,
+        });
+    }
+
+    // Second pass: encode
+    let mut rom = Vec::new();
+    for line in &lines {
+        encode_line(line, &labels, &mut rom)
+            .with_context(|| format!("Line {}: {}", line.number, line.mnemonic))?;
+    }
+
+    Ok(rom)
+}
+
+fn encode_line(line: &Line, labels: &HashMap<String, u16>, rom: &mut Vec<u8>) -> Result<()> {
+    let operands = line
+        .operands
+        .iter()
+        .map(|operand| parse_operand(operand, labels))
+        .collect::<Result<Vec<_>>>()?;
+
+    match line.mnemonic.as_str() {
+        "DB" => {
+            for operand in &operands {
+                rom.push(value(*operand, 0xFF)? as u8);
+            }
+            return Ok(());
+        }
+        "DW" => {
+            for operand in &operands {
+                rom.extend_from_slice(&value(*operand, 0xFFFF)?.to_be_bytes());
+            }
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    let opcode = encode_instruction(&line.mnemonic, &operands)?;
+    rom.extend_from_slice(&opcode.to_be_bytes());
+
+    Ok(())
+}
+
+fn encode_instruction(mnemonic: &str, operands: &[Operand]) -> Result<u16> {
+    use Operand::*;
+
+    let xy = |x: u8, y: u8| ((x as u16) << 8) | ((y as u16) << 4);
+
+    let opcode = match (mnemonic, operands) {
+        ("CLS", []) => 0x00E0,
+        ("RET", []) => 0x00EE,
+        ("SYS", [address]) => value(*address, 0xFFF)?,
+        ("JP", [Register(0), address]) => 0xB000 | value(*address, 0xFFF)?,
+        ("JP", [address]) => 0x1000 | value(*address, 0xFFF)?,
+        ("CALL", [address]) => 0x2000 | value(*address, 0xFFF)?,
+        ("SE", [Register(x), Register(y)]) => 0x5000 | xy(*x, *y),
+        ("SE", [Register(x), byte]) => 0x3000 | xy(*x, 0) | value(*byte, 0xFF)?,
+        ("SNE", [Register(x), Register(y)]) => 0x9000 | xy(*x, *y),
+        ("SNE", [Register(x), byte]) => 0x4000 | xy(*x, 0) | value(*byte, 0xFF)?,
+        ("LD", [Register(x), Register(y)]) => 0x8000 | xy(*x, *y),
+        ("LD", [Register(x), DelayTimer]) => 0xF007 | xy(*x, 0),
+        ("LD", [Register(x), Key]) => 0xF00A | xy(*x, 0),
+        ("LD", [Register(x), IndirectI]) => 0xF065 | xy(*x, 0),
+        ("LD", [Register(x), byte]) => 0x6000 | xy(*x, 0) | value(*byte, 0xFF)?,
+        ("LD", [I, address]) => 0xA000 | value(*address, 0xFFF)?,
+        ("LD", [DelayTimer, Register(x)]) => 0xF015 | xy(*x, 0),
+        ("LD", [SoundTimer, Register(x)]) => 0xF018 | xy(*x, 0),
+        ("LD", [Font, Register(x)]) => 0xF029 | xy(*x, 0),
+        ("LD", [Bcd, Register(x)]) => 0xF033 | xy(*x, 0),
+        ("LD", [IndirectI, Register(x)]) => 0xF055 | xy(*x, 0),
+        ("ADD", [I, Register(x)]) => 0xF01E | xy(*x, 0),
+        ("ADD", [Register(x), Register(y)]) => 0x8004 | xy(*x, *y),
+        ("ADD", [Register(x), byte]) => 0x7000 | xy(*x, 0) | value(*byte, 0xFF)?,
+        ("OR", [Register(x), Register(y)]) => 0x8001 | xy(*x, *y),
+        ("AND", [Register(x), Register(y)]) => 0x8002 | xy(*x, *y),
+        ("XOR", [Register(x), Register(y)]) => 0x8003 | xy(*x, *y),
+        ("SUB", [Register(x), Register(y)]) => 0x8005 | xy(*x, *y),
+        ("SHR", [Register(x)]) => 0x8006 | xy(*x, 0),
+        ("SHR", [Register(x), Register(y)]) => 0x8006 | xy(*x, *y),
+        ("SUBN", [Register(x), Register(y)]) => 0x8007 | xy(*x, *y),
+        ("SHL", [Register(x)]) => 0x800E | xy(*x, 0),
+        ("SHL", [Register(x), Register(y)]) => 0x800E | xy(*x, *y),
+        ("RND", [Register(x), byte]) => 0xC000 | xy(*x, 0) | value(*byte, 0xFF)?,
+        ("DRW", [Register(x), Register(y), height]) => 0xD000 | xy(*x, *y) | value(*height, 0xF)?,
+        ("SKP", [Register(x)]) => 0xE09E | xy(*x, 0),
+        ("SKNP", [Register(x)]) => 0xE0A1 | xy(*x, 0),
+        _ => bail!("invalid instruction or operands"),
+    };
+
+    Ok(opcode)
+}
+
+fn value(operand: Operand, max: u16) -> Result<u16> {
+    match operand {
+        Operand::Value(value) if value <= max => Ok(value),
+        Operand::Value(value) => bail!("value {:#X} is larger than {:#X}", value, max),
+        _ => bail!("expected a number or label, got {:?}", operand),
+    }
+}
+
+fn parse_operand(text: &str, labels: &HashMap<String, u16>) -> Result<Operand> {
+    let upper = text.to_ascii_uppercase();
+
+    let operand = match upper.as_str() {
+        "I" => Operand::I,
+        "[I]" => Operand::IndirectI,
+        "DT" => Operand::DelayTimer,
+        "ST" => Operand::SoundTimer,
+        "K" => Operand::Key,
+        "F" => Operand::Font,
+        "B" => Operand::Bcd,
+        _ => {
+            if let Some(register) = upper.strip_prefix('V') {
+                if let Ok(register) = u8::from_str_radix(register, 16) {
+                    if register < 0x10 {
+                        return Ok(Operand::Register(register));
+                    }
+                }
+            }
+            if let Some(&address) = labels.get(&text.to_ascii_lowercase()) {
+                return Ok(Operand::Value(address));
+            }
+            Operand::Value(parse_number(&upper)?)
+        }
+    };
+
+    Ok(operand)
+}
+
+fn parse_number(text: &str) -> Result<u16> {
+    let (digits, radix) = if let Some(digits) = text.strip_prefix("0X") {
+        (digits, 16)
+    } else if let Some(digits) = text.strip_prefix(['#', '$']) {
+        (digits, 16)
+    } else if let Some(digits) = text.strip_prefix("0B") {
+        (digits, 2)
+    } else {
+        (text, 10)
+    };
+
+    u16::from_str_radix(digits, radix).with_context(|| format!("invalid operand {:?}", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disassemble;
+
+    #[test]
+    fn every_disassembled_opcode_assembles_back() {
+        for opcode in 0..=u16::MAX {
+            if let Some(text) = disassemble::mnemonic(opcode) {
+                let rom = assemble(&text).unwrap();
+                assert_eq!(rom, opcode.to_be_bytes(), "{}", text);
+            }
+        }
+    }
+
+    #[test]
+    fn labels_resolve_forwards_and_backwards() {
+        let source = "
+            start:
+                JP end
+            loop: LD V0, 1
+                JP loop ; backwards
+            end: JP start
+        ";
+        assert_eq!(
+            assemble(source).unwrap(),
+            [0x12, 0x06, 0x60, 0x01, 0x12, 0x02, 0x12, 0x00]
+        );
+    }
+
+    #[test]
+    fn data_directives_emit_bytes_and_big_endian_words() {
+        let source = "
+            DB 1, 0xFF, #10
+            DW 0x1234, data
+            data: DB 0b101
+        ";
+        assert_eq!(
+            assemble(source).unwrap(),
+            [0x01, 0xFF, 0x10, 0x12, 0x34, 0x02, 0x07, 0x05]
+        );
+    }
+
+    #[test]
+    fn errors_report_the_line_number() {
+        let error = |source| assemble(source).unwrap_err().to_string();
+        assert_eq!(error("CLS\nLD V0, 0x100"), "Line 2: LD");
+        assert_eq!(error("CLS\n\nJP nowhere"), "Line 3: JP");
+        assert!(error("CLS\n: CLS").starts_with("Line 2: invalid label"));
+        assert!(error("CLS\nCLS\nx:\nx: RET").starts_with("Line 4: duplicate label"));
+    }
+
+    #[test]
+    fn registers_and_keywords_are_reserved_as_labels() {
+        for label in ["va", "V0", "f", "DT", "k"] {
+            let source = format!("{}: JP {}", label, label);
+            let error = assemble(&source).unwrap_err().to_string();
+            assert!(error.starts_with("Line 1:"), "{}", error);
+        }
+        assert_eq!(assemble("vf0: JP vf0").unwrap(), [0x12, 0x00]);
+    }
+}
@@ -1,15 +1,59 @@
-use sdl2::audio::AudioCallback;
+use anyhow::{anyhow, Result};
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use sdl2::AudioSubsystem;
+
+/// Plays the beep while the frontend says so, updated once per frame from
+/// the sound timer and the XO-CHIP audio pattern
+pub trait AudioBackend {
+    fn set_playing(&mut self, playing: bool);
+
+    /// `volume` ranges from 0.0 (silent) to 1.0
+    fn set_volume(&mut self, volume: f32);
+
+    /// Switches to playing the XO-CHIP 128-bit `pattern` at the rate given by `pitch`
+    fn set_pattern(&mut self, pattern: &[u8; 16], pitch: u8);
+}
+
+/// Plays the XO-CHIP audio pattern buffer, one bit per sample step
+pub struct PatternPlayer {
+    pattern: [u8; 16],
+    /// Position in bits, 0..128
+    position: f32,
+    /// Bits advanced per output sample
+    step: f32,
+}
+
+impl PatternPlayer {
+    fn next_sample(&mut self) -> bool {
+        let bit = self.position as usize;
+        let set = (self.pattern[bit / 8] >> (7 - bit % 8)) & 1 == 1;
+        self.position = (self.position + self.step) % 128.0;
+        set
+    }
+}
 
 pub struct SquareWave {
     pub phase_inc: f32,
     pub phase: f32,
     pub volume: f32,
+    pub pattern: Option<PatternPlayer>,
 }
 
 impl AudioCallback for SquareWave {
     type Channel = f32;
 
     fn callback(&mut self, out: &mut [f32]) {
+        if let Some(pattern) = &mut self.pattern {
+            for x in out.iter_mut() {
+                *x = if pattern.next_sample() {
+                    self.volume
+                } else {
+                    -self.volume
+                };
+            }
+            return;
+        }
+
         for x in out.iter_mut() {
             *x = if self.phase <= 0.5 {
                 self.volume
@@ -20,3 +64,152 @@ impl AudioCallback for SquareWave {
         }
     }
 }
+
+pub struct SdlAudio {
+    device: AudioDevice<SquareWave>,
+}
+
+impl SdlAudio {
+    /// Opens a paused 200Hz square wave device
+    pub fn new(audio_subsystem: &AudioSubsystem, volume: f32) -> Result<Self> {
+        let desired_spec = AudioSpecDesired {
+            freq: Some(44_100),
+            channels: Some(1),
+            samples: None,
+        };
+
+        let device = audio_subsystem
+            .open_playback(None, &desired_spec, |spec| SquareWave {
+                phase_inc: 200.0 / spec.freq as f32,
+                phase: 0.0,
+                volume,
+                pattern: None,
+            })
+            .map_err(|err| anyhow!(err))?;
+
+        Ok(Self { device })
+    }
+}
+
+impl AudioBackend for SdlAudio {
+    fn set_playing(&mut self, playing: bool) {
+        if playing {
+            self.device.resume();
+        } else {
+            self.device.pause();
+        }
+    }
+
+    fn set_volume(&mut self, volume: f32) {
+        self.device.lock().volume = volume;
+    }
+
+    fn set_pattern(&mut self, pattern: &[u8; 16], pitch: u8) {
+        // XO-CHIP plays 4000 bits per second at pitch 64, one octave per 48 steps
+        let rate = 4000.0 * 2f32.powf((pitch as f32 - 64.0) / 48.0);
+        let step = rate / self.device.spec().freq as f32;
+
+        let mut wave = self.device.lock();
+        match &mut wave.pattern {
+            // Keep the position so updating an unchanged pattern doesn't click
+            Some(player) => {
+                player.pattern = *pattern;
+                player.step = step;
+            }
+            None => {
+                wave.pattern = Some(PatternPlayer {
+                    pattern: *pattern,
+                    position: 0.0,
+                    step,
+                })
+            }
+        }
+    }
+}
+
+/// Plays nothing, for headless runs and machines without an audio device
+pub struct NullAudio;
+
+impl AudioBackend for NullAudio {
+    fn set_playing(&mut self, _playing: bool) {}
+
+    fn set_volume(&mut self, _volume: f32) {}
+
+    fn set_pattern(&mut self, _pattern: &[u8; 16], _pitch: u8) {}
+}
+
+/// SDL doesnt alway play audio if it only lasts for 1 frame
+const MIN_BEEP_FRAMES: u8 = 2;
+
+/// Decides once per frame whether the beep plays.
+///
+/// The beep follows the sound timer exactly, except that a beep shorter than
+/// `MIN_BEEP_FRAMES` is held until it reaches that length. Re-triggering the
+/// sound timer every frame keeps it playing without a gap, so continuous
+/// tones don't click.
+#[derive(Default)]
+pub struct BeepLatch {
+    playing: bool,
+    played_frames: u8,
+}
+
+impl BeepLatch {
+    /// Returns true if the playing state changed
+    pub fn update(&mut self, beeping: bool) -> bool {
+        let playing = beeping || (self.playing && self.played_frames < MIN_BEEP_FRAMES);
+        self.played_frames = if playing {
+            self.played_frames.saturating_add(1)
+        } else {
+            0
+        };
+
+        let changed = playing != self.playing;
+        self.playing = playing;
+        changed
+    }
+
+    pub fn playing(&self) -> bool {
+        self.playing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::virtual_machine::VirtualMachine;
+
+    #[test]
+    fn beep_stops_when_the_sound_timer_reaches_zero() {
+        // Sound timer = 3, then idle
+        let mut machine =
+            VirtualMachine::from_bytes(&[0x60, 0x03, 0xF0, 0x18, 0x12, 0x04]).unwrap();
+        let mut latch = BeepLatch::default();
+
+        machine.run_frame(2, |_, _| false).unwrap();
+        latch.update(machine.is_beeping());
+        assert!(latch.playing());
+
+        for expected in [2, 1, 0] {
+            machine.run_frame(1, |_, _| false).unwrap();
+            assert_eq!(machine.sound_timer, expected);
+            latch.update(machine.is_beeping());
+            assert_eq!(latch.playing(), expected > 0);
+        }
+    }
+
+    #[test]
+    fn retriggering_the_sound_timer_every_frame_keeps_playing() {
+        // Sound timer = 1 once per frame
+        let mut machine =
+            VirtualMachine::from_bytes(&[0x60, 0x01, 0xF0, 0x18, 0x12, 0x02]).unwrap();
+        let mut latch = BeepLatch::default();
+
+        machine.run_frame(2, |_, _| false).unwrap();
+        assert!(latch.update(machine.is_beeping()));
+        for _ in 0..60 {
+            machine.run_frame(2, |_, _| false).unwrap();
+            assert!(!latch.update(machine.is_beeping()));
+            assert!(latch.playing());
+        }
+    }
+}
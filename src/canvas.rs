@@ -0,0 +1,168 @@
+/// Monochrome bit-grid. Rows are packed into 64-bit words with x = 0 in the
+/// lowest bit of a row's first word.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Canvas {
+    width: usize,
+    height: usize,
+    words: Vec<u64>,
+}
+
+impl Canvas {
+    /// `width` must be a multiple of 64
+    pub fn new(width: usize, height: usize) -> Self {
+        assert!(
+            width > 0 && width.is_multiple_of(64),
+            "Canvas width must be a multiple of 64, got {}",
+            width
+        );
+
+        Self {
+            width,
+            height,
+            words: vec![0; width / 64 * height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Word index and bit of the pixel at `x`, `y`
+    fn position(&self, x: usize, y: usize) -> (usize, usize) {
+        debug_assert!(x < self.width && y < self.height, "Pixel out of bounds");
+        (y * self.width / 64 + x / 64, x % 64)
+    }
+
+    pub fn get_pixel(&self, x: usize, y: usize) -> bool {
+        let (word, bit) = self.position(x, y);
+        (self.words[word] >> bit) & 1 == 1
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, on: bool) {
+        let (word, bit) = self.position(x, y);
+        if on {
+            self.words[word] |= 1 << bit;
+        } else {
+            self.words[word] &= !(1 << bit);
+        }
+    }
+
+    /// XORs the 8 pixels of `bits`, most significant bit first, into row `y`
+    /// starting at `x`. Pixels past the right edge continue on the left with
+    /// `wrap` and are clipped otherwise. Returns whether any pixel was turned off.
+    pub fn xor_row(&mut self, x: usize, y: usize, bits: u8, wrap: bool) -> bool {
+        let (word, bit) = self.position(x, y);
+        let row_start = y * self.width / 64;
+        let row_end = row_start + self.width / 64;
+
+        let mut collision = false;
+        let mut xor = |index: usize, part: u64| {
+            collision |= self.words[index] & part != 0;
+            self.words[index] ^= part;
+        };
+
+        // The full width keeps the pixels that spill into the next word
+        let wide = (bits.reverse_bits() as u128) << bit;
+        xor(word, wide as u64);
+        let spill = (wide >> 64) as u64;
+        if spill != 0 {
+            if word + 1 < row_end {
+                xor(word + 1, spill);
+            } else if wrap {
+                xor(row_start, spill);
+            }
+        }
+
+        collision
+    }
+
+    /// Sets every pixel that is on in `before` but off in `after`
+    pub fn mark_turned_off(&mut self, before: &Canvas, after: &Canvas) {
+        for ((word, before), after) in self.words.iter_mut().zip(&before.words).zip(&after.words) {
+            *word |= before & !after;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.words.fill(0);
+    }
+
+    /// Every pixel as `(x, y, on)`, row by row
+    pub fn iter_pixels(&self) -> impl Iterator<Item = (usize, usize, bool)> + '_ {
+        (0..self.height)
+            .flat_map(move |y| (0..self.width).map(move |x| (x, y, self.get_pixel(x, y))))
+    }
+
+    /// The packed rows, for serialization
+    pub fn words(&self) -> &[u64] {
+        &self.words
+    }
+
+    pub fn words_mut(&mut self) -> &mut [u64] {
+        &mut self.words
+    }
+}
+
+/// Renders both planes as tightly packed RGBA pixels, `scale`×`scale` per
+/// CHIP-8 pixel, coloring each pixel with the `palette` entry of its color index
+pub fn render_rgba(planes: &[Canvas; 2], scale: usize, palette: &[[u8; 4]; 4]) -> Vec<u8> {
+    let [plane0, plane1] = planes;
+    let (width, height) = (plane0.width(), plane0.height());
+
+    let mut pixels = Vec::with_capacity(width * height * scale * scale * 4);
+    for y in 0..height {
+        for _ in 0..scale {
+            for x in 0..width {
+                let color =
+                    plane0.get_pixel(x, y) as usize | ((plane1.get_pixel(x, y) as usize) << 1);
+                for _ in 0..scale {
+                    pixels.extend_from_slice(&palette[color]);
+                }
+            }
+        }
+    }
+
+    pixels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_pixel_round_trips_through_get_pixel() {
+        let mut canvas = Canvas::new(128, 64);
+        canvas.set_pixel(127, 63, true);
+        canvas.set_pixel(64, 0, true);
+        assert!(canvas.get_pixel(127, 63));
+        assert!(canvas.get_pixel(64, 0));
+        assert!(!canvas.get_pixel(63, 0));
+
+        canvas.set_pixel(64, 0, false);
+        assert!(!canvas.get_pixel(64, 0));
+        assert_eq!(canvas.iter_pixels().filter(|&(_, _, on)| on).count(), 1);
+    }
+
+    #[test]
+    fn xor_row_crosses_words_and_wraps_or_clips_at_the_edge() {
+        let mut canvas = Canvas::new(128, 1);
+        assert!(!canvas.xor_row(60, 0, 0xFF, false));
+        assert!((60..68).all(|x| canvas.get_pixel(x, 0)));
+
+        canvas.clear();
+        canvas.xor_row(124, 0, 0xFF, false);
+        assert!((124..128).all(|x| canvas.get_pixel(x, 0)));
+        assert!(!canvas.get_pixel(0, 0));
+
+        canvas.clear();
+        canvas.xor_row(124, 0, 0xFF, true);
+        assert!((0..4).all(|x| canvas.get_pixel(x, 0)));
+        assert!(canvas.xor_row(0, 0, 0x80, true));
+        assert!(!canvas.get_pixel(0, 0));
+    }
+}
@@ -16,3 +16,103 @@ pub const CHARS: [u8; 80] = [
     0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
+
+/// The DREAM 6800 font, 3 pixels wide
+const DREAM_6800: [u8; 80] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, // 0
+    0x40, 0x40, 0x40, 0x40, 0x40, // 1
+    0xE0, 0x20, 0xE0, 0x80, 0xE0, // 2
+    0xE0, 0x20, 0xE0, 0x20, 0xE0, // 3
+    0x80, 0xA0, 0xA0, 0xE0, 0x20, // 4
+    0xE0, 0x80, 0xE0, 0x20, 0xE0, // 5
+    0xE0, 0x80, 0xE0, 0xA0, 0xE0, // 6
+    0xE0, 0x20, 0x20, 0x20, 0x20, // 7
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0, // 8
+    0xE0, 0xA0, 0xE0, 0x20, 0xE0, // 9
+    0xE0, 0xA0, 0xE0, 0xA0, 0xA0, // A
+    0xC0, 0xA0, 0xE0, 0xA0, 0xC0, // B
+    0xE0, 0x80, 0x80, 0x80, 0xE0, // C
+    0xC0, 0xA0, 0xA0, 0xA0, 0xC0, // D
+    0xE0, 0x80, 0xE0, 0x80, 0xE0, // E
+    0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+];
+
+/// The ETI 660 font, 3 pixels wide
+const ETI_660: [u8; 80] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, // 0
+    0x20, 0x20, 0x20, 0x20, 0x20, // 1
+    0xE0, 0x20, 0xE0, 0x80, 0xE0, // 2
+    0xE0, 0x20, 0xE0, 0x20, 0xE0, // 3
+    0xA0, 0xA0, 0xE0, 0x20, 0x20, // 4
+    0xE0, 0x80, 0xE0, 0x20, 0xE0, // 5
+    0xE0, 0x80, 0xE0, 0xA0, 0xE0, // 6
+    0xE0, 0x20, 0x20, 0x20, 0x20, // 7
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0, // 8
+    0xE0, 0xA0, 0xE0, 0x20, 0xE0, // 9
+    0xE0, 0xA0, 0xE0, 0xA0, 0xA0, // A
+    0x80, 0x80, 0xE0, 0xA0, 0xE0, // B
+    0xE0, 0x80, 0x80, 0x80, 0xE0, // C
+    0x20, 0x20, 0xE0, 0xA0, 0xE0, // D
+    0xE0, 0x80, 0xE0, 0x80, 0xE0, // E
+    0xE0, 0x80, 0xE0, 0x80, 0x80, // F
+];
+
+/// Bundled fonts by name, the first one is loaded by default
+pub const FONTS: [(&str, &[u8; 80]); 3] = [
+    ("vip", &CHARS),
+    ("dream6800", &DREAM_6800),
+    ("eti660", &ETI_660),
+];
+
+pub fn font(name: &str) -> Option<&'static [u8; 80]> {
+    FONTS
+        .iter()
+        .find(|(font, _)| font.eq_ignore_ascii_case(name))
+        .map(|&(_, font)| font)
+}
+
+/// Letters and punctuation missing from [`CHARS`], used for on-screen text.
+/// Glyphs use the upper nibble of each row like the CHIP-8 font.
+const LETTERS: [[u8; 5]; 20] = [
+    [0xF0, 0x80, 0xB0, 0x90, 0xF0], // G
+    [0x90, 0x90, 0xF0, 0x90, 0x90], // H
+    [0xE0, 0x40, 0x40, 0x40, 0xE0], // I
+    [0x10, 0x10, 0x10, 0x90, 0x60], // J
+    [0x90, 0xA0, 0xC0, 0xA0, 0x90], // K
+    [0x80, 0x80, 0x80, 0x80, 0xF0], // L
+    [0x90, 0xF0, 0xF0, 0x90, 0x90], // M
+    [0x90, 0xD0, 0xB0, 0x90, 0x90], // N
+    [0x60, 0x90, 0x90, 0x90, 0x60], // O
+    [0xE0, 0x90, 0xE0, 0x80, 0x80], // P
+    [0x60, 0x90, 0x90, 0xB0, 0x70], // Q
+    [0xE0, 0x90, 0xE0, 0xA0, 0x90], // R
+    [0x70, 0x80, 0x60, 0x10, 0xE0], // S
+    [0xE0, 0x40, 0x40, 0x40, 0x40], // T
+    [0x90, 0x90, 0x90, 0x90, 0x60], // U
+    [0xA0, 0xA0, 0xA0, 0xA0, 0x40], // V
+    [0x90, 0x90, 0xF0, 0xF0, 0x90], // W
+    [0x90, 0x90, 0x60, 0x90, 0x90], // X
+    [0xA0, 0xA0, 0x40, 0x40, 0x40], // Y
+    [0xF0, 0x10, 0x60, 0x80, 0xF0], // Z
+];
+
+/// Returns the 4x5 glyph for `c`, lowercase letters share uppercase glyphs
+pub fn glyph(c: char) -> Option<[u8; 5]> {
+    let c = c.to_ascii_uppercase();
+    let glyph = match c {
+        '0'..='9' | 'A'..='F' => {
+            let index = c.to_digit(16)? as usize * 5;
+            CHARS[index..index + 5].try_into().ok()?
+        }
+        'G'..='Z' => LETTERS[c as usize - 'G' as usize],
+        ' ' => [0x00, 0x00, 0x00, 0x00, 0x00],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x40],
+        ':' => [0x00, 0x40, 0x00, 0x40, 0x00],
+        '-' => [0x00, 0x00, 0xE0, 0x00, 0x00],
+        '/' => [0x10, 0x20, 0x20, 0x40, 0x80],
+        '%' => [0x90, 0x10, 0x20, 0x40, 0x90],
+        _ => return None,
+    };
+
+    Some(glyph)
+}
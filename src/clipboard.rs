@@ -0,0 +1,69 @@
+use std::borrow::Cow;
+
+use anyhow::Result;
+use arboard::{Clipboard, ImageData};
+
+use crate::virtual_machine::VirtualMachine;
+use crate::{HEIGHT, WIDTH};
+
+/// Copies the screen in the window colors
+pub fn copy_canvas(
+    machine: &VirtualMachine,
+    scale: u32,
+    foreground: (u8, u8, u8),
+    background: (u8, u8, u8),
+) -> Result<()> {
+    copy_canvas_with(machine, scale, foreground, background, |image| {
+        Clipboard::new()?.set_image(image)?;
+        Ok(())
+    })
+}
+
+/// Renders the screen and hands the image to `write`, which stands in for
+/// the clipboard in tests
+fn copy_canvas_with(
+    machine: &VirtualMachine,
+    scale: u32,
+    foreground: (u8, u8, u8),
+    background: (u8, u8, u8),
+    write: impl FnOnce(ImageData<'static>) -> Result<()>,
+) -> Result<()> {
+    let image = ImageData {
+        width: WIDTH * scale as usize,
+        height: HEIGHT * scale as usize,
+        bytes: Cow::Owned(machine.render_to_buffer(scale, foreground, background)),
+    };
+
+    write(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copied_image_matches_the_rendered_screen() {
+        // Draw the glyph for 0 at the top left
+        let mut machine =
+            VirtualMachine::from_bytes(&[0x00, 0xE0, 0xA0, 0x50, 0xD0, 0x05]).unwrap();
+        for _ in 0..3 {
+            machine.step().unwrap();
+        }
+        let (foreground, background) = ((0xFF, 0xB0, 0x00), (0x1A, 0x10, 0x00));
+
+        let mut copied = None;
+        copy_canvas_with(&machine, 2, foreground, background, |image| {
+            copied = Some(image);
+            Ok(())
+        })
+        .unwrap();
+
+        let image = copied.unwrap();
+        assert_eq!((image.width, image.height), (WIDTH * 2, HEIGHT * 2));
+        assert_eq!(
+            image.bytes,
+            machine.render_to_buffer(2, foreground, background)
+        );
+        assert_eq!(image.bytes[..4], [0xFF, 0xB0, 0x00, 0xFF]);
+    }
+}
@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+
+const KNOWN_KEYS: [&str; 6] = [
+    "frequency",
+    "scale",
+    "foreground",
+    "background",
+    "keys",
+    "quirks",
+];
+const KNOWN_QUIRKS: [&str; 11] = [
+    "rom_readonly",
+    "strict",
+    "xo_chip",
+    "stack_wrap",
+    "jump_with_vx",
+    "logic_resets_vf",
+    "load_store_increments_i",
+    "wrap_sprites",
+    "i_overflow_sets_vf",
+    "halt_on_idle",
+    "halt_on_top_return",
+];
+
+/// Settings read from a `--config` TOML file. Explicit command line flags
+/// take precedence over anything set here.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub frequency: Option<u32>,
+    pub scale: Option<u32>,
+    /// `#RRGGBB` color of set pixels
+    pub foreground: Option<String>,
+    /// `#RRGGBB` color of unset pixels
+    pub background: Option<String>,
+    /// Extra bindings from a hexadecimal CHIP-8 key to an SDL key name, e.g. `5 = "Up"`
+    pub keys: HashMap<String, String>,
+    pub quirks: Quirks,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Quirks {
+    pub rom_readonly: bool,
+    pub strict: bool,
+    pub xo_chip: bool,
+    pub stack_wrap: bool,
+    pub jump_with_vx: bool,
+    pub logic_resets_vf: bool,
+    /// `none`, `x` or `x+1`
+    pub load_store_increments_i: Option<String>,
+    pub wrap_sprites: bool,
+    pub i_overflow_sets_vf: bool,
+    pub halt_on_idle: bool,
+    pub halt_on_top_return: bool,
+}
+
+impl Config {
+    /// Unknown keys are reported on stderr and otherwise ignored
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config: {:?}", path))?;
+        let table: toml::Table =
+            toml::from_str(&text).with_context(|| format!("Invalid config: {:?}", path))?;
+
+        for key in table.keys() {
+            if !KNOWN_KEYS.contains(&key.as_str()) {
+                eprintln!("Ignoring unknown config key: {}", key);
+            }
+        }
+        if let Some(toml::Value::Table(quirks)) = table.get("quirks") {
+            for key in quirks.keys() {
+                if !KNOWN_QUIRKS.contains(&key.as_str()) {
+                    eprintln!("Ignoring unknown quirk: {}", key);
+                }
+            }
+        }
+
+        toml::Value::Table(table)
+            .try_into()
+            .with_context(|| format!("Invalid config: {:?}", path))
+    }
+
+    /// Foreground and background colors. `--fg` and `--bg` take precedence
+    /// over `--theme`, which takes precedence over the config file.
+    pub fn colors(
+        &self,
+        theme_name: Option<&str>,
+        foreground: Option<&str>,
+        background: Option<&str>,
+    ) -> Result<(Rgb, Rgb)> {
+        let (theme_foreground, theme_background) = theme(theme_name.unwrap_or("default"))?;
+        let pick = |flag: Option<&str>, configured: &Option<String>, themed| match flag {
+            Some(color) => parse_color(color),
+            None => match configured {
+                Some(color) if theme_name.is_none() => parse_color(color),
+                _ => Ok(themed),
+            },
+        };
+
+        Ok((
+            pick(foreground, &self.foreground, theme_foreground)?,
+            pick(background, &self.background, theme_background)?,
+        ))
+    }
+}
+
+/// Red, green and blue channels of a color
+pub type Rgb = (u8, u8, u8);
+
+/// Built-in `--theme` presets as foreground and background colors
+const THEMES: [(&str, Rgb, Rgb); 5] = [
+    ("default", (0x00, 0x00, 0x00), (0xFF, 0xFF, 0xFF)),
+    ("gameboy", (0x0F, 0x38, 0x0F), (0x9B, 0xBC, 0x0F)),
+    ("amber", (0xFF, 0xB0, 0x00), (0x1A, 0x10, 0x00)),
+    ("c64", (0x6C, 0x5E, 0xB5), (0x35, 0x28, 0x79)),
+    ("inverted", (0xFF, 0xFF, 0xFF), (0x00, 0x00, 0x00)),
+];
+
+/// Foreground and background colors of the theme called `name`
+pub fn theme(name: &str) -> Result<(Rgb, Rgb)> {
+    match THEMES.iter().find(|(theme, _, _)| *theme == name) {
+        Some(&(_, foreground, background)) => Ok((foreground, background)),
+        None => {
+            let names: Vec<_> = THEMES.iter().map(|(theme, _, _)| *theme).collect();
+            bail!("Unknown theme: {} (expected {})", name, names.join(", "))
+        }
+    }
+}
+
+/// Parses `#RRGGBB` into its components
+pub fn parse_color(text: &str) -> Result<Rgb> {
+    let digits = text.strip_prefix('#').unwrap_or(text);
+    if digits.len() != 6 {
+        bail!("Invalid color: {} (expected #RRGGBB)", text);
+    }
+    let value =
+        u32::from_str_radix(digits, 16).with_context(|| format!("Invalid color: {}", text))?;
+
+    Ok(((value >> 16) as u8, (value >> 8) as u8, value as u8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flag_colors_win_over_the_theme_which_wins_over_the_config() {
+        let config = Config {
+            foreground: Some("#112233".to_string()),
+            background: Some("#445566".to_string()),
+            ..Config::default()
+        };
+        let amber = theme("amber").unwrap();
+
+        assert_eq!(
+            config.colors(None, None, None).unwrap(),
+            ((0x11, 0x22, 0x33), (0x44, 0x55, 0x66))
+        );
+        assert_eq!(config.colors(Some("amber"), None, None).unwrap(), amber);
+        assert_eq!(
+            config.colors(Some("amber"), Some("#ABCDEF"), None).unwrap(),
+            ((0xAB, 0xCD, 0xEF), amber.1)
+        );
+        assert_eq!(
+            Config::default().colors(None, None, None).unwrap(),
+            theme("default").unwrap()
+        );
+    }
+}
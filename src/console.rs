@@ -0,0 +1,82 @@
+use std::io::BufRead;
+use std::sync::mpsc::{self, Receiver};
+
+use anyhow::{bail, Context, Result};
+
+use crate::characters;
+use crate::virtual_machine::VirtualMachine;
+
+/// A `--console` command typed on stdin while the emulator runs
+pub enum Command {
+    Memory(u16, u8),
+    Register(u8, u8),
+    Font(&'static [u8; 80]),
+}
+
+impl Command {
+    /// Accepts `set mem ADDR = VALUE` and `set VX = VALUE` with hexadecimal
+    /// numbers, and `font NAME` with a bundled font
+    pub fn parse(line: &str) -> Result<Self> {
+        let words: Vec<_> = line.split_whitespace().collect();
+        match words[..] {
+            ["set", "mem", address, "=", value] => {
+                let address = crate::parse_hex(address, "address")?;
+                Ok(Command::Memory(address, parse_byte(value)?))
+            }
+            ["set", register, "=", value] => {
+                let register = register
+                    .strip_prefix(['V', 'v'])
+                    .filter(|digit| digit.len() == 1)
+                    .and_then(|digit| u8::from_str_radix(digit, 16).ok())
+                    .with_context(|| format!("Invalid register: {}", register))?;
+                Ok(Command::Register(register, parse_byte(value)?))
+            }
+            ["font", name] => {
+                let font = characters::font(name).with_context(|| {
+                    let names: Vec<_> = characters::FONTS.iter().map(|(name, _)| *name).collect();
+                    format!("Unknown font: {} (expected {})", name, names.join(", "))
+                })?;
+                Ok(Command::Font(font))
+            }
+            _ => bail!(
+                "Unknown command: {} (expected set mem ADDR = VALUE, set VX = VALUE or font NAME)",
+                line
+            ),
+        }
+    }
+
+    pub fn apply(&self, machine: &mut VirtualMachine) -> Result<()> {
+        match *self {
+            Command::Memory(address, value) => machine.poke_memory(address, value),
+            Command::Register(register, value) => machine.poke_register(register, value),
+            Command::Font(font) => {
+                machine.switch_font(font);
+                Ok(())
+            }
+        }
+    }
+}
+
+fn parse_byte(text: &str) -> Result<u8> {
+    crate::parse_hex(text, "value")
+        .ok()
+        .and_then(|value| u8::try_from(value).ok())
+        .with_context(|| format!("Invalid value: {} (expected 00 to FF)", text))
+}
+
+/// Reads stdin on its own thread so waiting for input never blocks emulation
+pub fn spawn() -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    receiver
+}
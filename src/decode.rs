@@ -0,0 +1,170 @@
+/// One opcode split into its operation and operands, with a variant per match
+/// arm of `VirtualMachine::execute_opcode`. The disassembler decodes through
+/// it too, so both always agree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodedOp {
+    /// 00E0
+    ClearScreen,
+    /// 00EE
+    Return,
+    /// 0NNN
+    MachineCall(u16),
+    /// 1NNN
+    Jump(u16),
+    /// 2NNN
+    Call(u16),
+    /// 3XNN
+    SkipIfEqual { x: u8, byte: u8 },
+    /// 4XNN
+    SkipIfNotEqual { x: u8, byte: u8 },
+    /// 5XY0
+    SkipIfRegistersEqual { x: u8, y: u8 },
+    /// 6XNN
+    Load { x: u8, byte: u8 },
+    /// 7XNN
+    AddByte { x: u8, byte: u8 },
+    /// 8XY0
+    Copy { x: u8, y: u8 },
+    /// 8XY1
+    Or { x: u8, y: u8 },
+    /// 8XY2
+    And { x: u8, y: u8 },
+    /// 8XY3
+    Xor { x: u8, y: u8 },
+    /// 8XY4
+    Add { x: u8, y: u8 },
+    /// 8XY5
+    Sub { x: u8, y: u8 },
+    /// 8XY6
+    ShiftRight { x: u8, y: u8 },
+    /// 8XY7
+    SubReverse { x: u8, y: u8 },
+    /// 8XYE
+    ShiftLeft { x: u8, y: u8 },
+    /// 9XY0
+    SkipIfRegistersNotEqual { x: u8, y: u8 },
+    /// ANNN
+    LoadI(u16),
+    /// BNNN
+    JumpOffset(u16),
+    /// CXNN
+    Random { x: u8, mask: u8 },
+    /// DXYN
+    Draw { x: u8, y: u8, height: u8 },
+    /// EX9E
+    SkipIfKey(u8),
+    /// EXA1
+    SkipIfNotKey(u8),
+    /// FN01, XO-CHIP only
+    SelectPlanes(u8),
+    /// F002, XO-CHIP only
+    LoadAudioPattern,
+    /// FX07
+    ReadDelayTimer(u8),
+    /// FX0A
+    WaitForKey(u8),
+    /// FX15
+    SetDelayTimer(u8),
+    /// FX18
+    SetSoundTimer(u8),
+    /// FX1E
+    AddToI(u8),
+    /// FX29
+    LoadFont(u8),
+    /// FX33
+    StoreBcd(u8),
+    /// FX3A, XO-CHIP only
+    SetPitch(u8),
+    /// FX55
+    StoreRegisters(u8),
+    /// FX65
+    LoadRegisters(u8),
+    /// Anything `execute_opcode` treats as an invalid opcode
+    Unknown(u16),
+}
+
+impl DecodedOp {
+    pub fn decode(opcode: u16, xo_chip: bool) -> Self {
+        let address = opcode & 0x0FFF;
+        let x = ((opcode >> 8) & 0xF) as u8;
+        let y = ((opcode >> 4) & 0xF) as u8;
+        let byte = (opcode & 0xFF) as u8;
+        let nibble = (opcode & 0xF) as u8;
+
+        match opcode >> 12 {
+            0x0 => match opcode {
+                0x00E0 => DecodedOp::ClearScreen,
+                0x00EE => DecodedOp::Return,
+                _ => DecodedOp::MachineCall(address),
+            },
+            0x1 => DecodedOp::Jump(address),
+            0x2 => DecodedOp::Call(address),
+            0x3 => DecodedOp::SkipIfEqual { x, byte },
+            0x4 => DecodedOp::SkipIfNotEqual { x, byte },
+            0x5 if nibble == 0 => DecodedOp::SkipIfRegistersEqual { x, y },
+            0x6 => DecodedOp::Load { x, byte },
+            0x7 => DecodedOp::AddByte { x, byte },
+            0x8 => match nibble {
+                0x0 => DecodedOp::Copy { x, y },
+                0x1 => DecodedOp::Or { x, y },
+                0x2 => DecodedOp::And { x, y },
+                0x3 => DecodedOp::Xor { x, y },
+                0x4 => DecodedOp::Add { x, y },
+                0x5 => DecodedOp::Sub { x, y },
+                0x6 => DecodedOp::ShiftRight { x, y },
+                0x7 => DecodedOp::SubReverse { x, y },
+                0xE => DecodedOp::ShiftLeft { x, y },
+                _ => DecodedOp::Unknown(opcode),
+            },
+            0x9 if nibble == 0 => DecodedOp::SkipIfRegistersNotEqual { x, y },
+            0xA => DecodedOp::LoadI(address),
+            0xB => DecodedOp::JumpOffset(address),
+            0xC => DecodedOp::Random { x, mask: byte },
+            0xD => DecodedOp::Draw {
+                x,
+                y,
+                height: nibble,
+            },
+            0xE => match byte {
+                0x9E => DecodedOp::SkipIfKey(x),
+                0xA1 => DecodedOp::SkipIfNotKey(x),
+                _ => DecodedOp::Unknown(opcode),
+            },
+            0xF => match byte {
+                0x01 if xo_chip => DecodedOp::SelectPlanes(x & 0b11),
+                0x02 if xo_chip && x == 0 => DecodedOp::LoadAudioPattern,
+                0x07 => DecodedOp::ReadDelayTimer(x),
+                0x0A => DecodedOp::WaitForKey(x),
+                0x15 => DecodedOp::SetDelayTimer(x),
+                0x18 => DecodedOp::SetSoundTimer(x),
+                0x1E => DecodedOp::AddToI(x),
+                0x29 => DecodedOp::LoadFont(x),
+                0x33 => DecodedOp::StoreBcd(x),
+                0x3A if xo_chip => DecodedOp::SetPitch(x),
+                0x55 => DecodedOp::StoreRegisters(x),
+                0x65 => DecodedOp::LoadRegisters(x),
+                _ => DecodedOp::Unknown(opcode),
+            },
+            _ => DecodedOp::Unknown(opcode),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_00e0_and_00ee_clear_and_return() {
+        assert_eq!(DecodedOp::decode(0x00E0, false), DecodedOp::ClearScreen);
+        assert_eq!(DecodedOp::decode(0x00EE, false), DecodedOp::Return);
+        assert_eq!(
+            DecodedOp::decode(0x01E0, false),
+            DecodedOp::MachineCall(0x1E0)
+        );
+        assert_eq!(
+            DecodedOp::decode(0x02EE, false),
+            DecodedOp::MachineCall(0x2EE)
+        );
+    }
+}
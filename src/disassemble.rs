@@ -0,0 +1,163 @@
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+use anyhow::{bail, Result};
+
+use crate::decode::DecodedOp;
+
+/// Formats `opcode` with the mnemonics accepted by the assembler,
+/// returns None for invalid opcodes
+pub fn mnemonic(opcode: u16) -> Option<String> {
+    // Without XO-CHIP the decoder never returns its opcodes, which the
+    // assembler doesn't know either
+    let text = match DecodedOp::decode(opcode, false) {
+        DecodedOp::ClearScreen => "CLS".to_string(),
+        DecodedOp::Return => "RET".to_string(),
+        DecodedOp::MachineCall(address) => format!("SYS {:#05X}", address),
+        DecodedOp::Jump(address) => format!("JP {:#05X}", address),
+        DecodedOp::Call(address) => format!("CALL {:#05X}", address),
+        DecodedOp::SkipIfEqual { x, byte } => format!("SE V{:X}, {:#04X}", x, byte),
+        DecodedOp::SkipIfNotEqual { x, byte } => format!("SNE V{:X}, {:#04X}", x, byte),
+        DecodedOp::SkipIfRegistersEqual { x, y } => format!("SE V{:X}, V{:X}", x, y),
+        DecodedOp::Load { x, byte } => format!("LD V{:X}, {:#04X}", x, byte),
+        DecodedOp::AddByte { x, byte } => format!("ADD V{:X}, {:#04X}", x, byte),
+        DecodedOp::Copy { x, y } => format!("LD V{:X}, V{:X}", x, y),
+        DecodedOp::Or { x, y } => format!("OR V{:X}, V{:X}", x, y),
+        DecodedOp::And { x, y } => format!("AND V{:X}, V{:X}", x, y),
+        DecodedOp::Xor { x, y } => format!("XOR V{:X}, V{:X}", x, y),
+        DecodedOp::Add { x, y } => format!("ADD V{:X}, V{:X}", x, y),
+        DecodedOp::Sub { x, y } => format!("SUB V{:X}, V{:X}", x, y),
+        DecodedOp::ShiftRight { x, y } => format!("SHR V{:X}, V{:X}", x, y),
+        DecodedOp::SubReverse { x, y } => format!("SUBN V{:X}, V{:X}", x, y),
+        DecodedOp::ShiftLeft { x, y } => format!("SHL V{:X}, V{:X}", x, y),
+        DecodedOp::SkipIfRegistersNotEqual { x, y } => format!("SNE V{:X}, V{:X}", x, y),
+        DecodedOp::LoadI(address) => format!("LD I, {:#05X}", address),
+        DecodedOp::JumpOffset(address) => format!("JP V0, {:#05X}", address),
+        DecodedOp::Random { x, mask } => format!("RND V{:X}, {:#04X}", x, mask),
+        DecodedOp::Draw { x, y, height } => format!("DRW V{:X}, V{:X}, {}", x, y, height),
+        DecodedOp::SkipIfKey(x) => format!("SKP V{:X}", x),
+        DecodedOp::SkipIfNotKey(x) => format!("SKNP V{:X}", x),
+        DecodedOp::ReadDelayTimer(x) => format!("LD V{:X}, DT", x),
+        DecodedOp::WaitForKey(x) => format!("LD V{:X}, K", x),
+        DecodedOp::SetDelayTimer(x) => format!("LD DT, V{:X}", x),
+        DecodedOp::SetSoundTimer(x) => format!("LD ST, V{:X}", x),
+        DecodedOp::AddToI(x) => format!("ADD I, V{:X}", x),
+        DecodedOp::LoadFont(x) => format!("LD F, V{:X}", x),
+        DecodedOp::StoreBcd(x) => format!("LD B, V{:X}", x),
+        DecodedOp::StoreRegisters(x) => format!("LD [I], V{:X}", x),
+        DecodedOp::LoadRegisters(x) => format!("LD V{:X}, [I]", x),
+        DecodedOp::SelectPlanes(_)
+        | DecodedOp::LoadAudioPattern
+        | DecodedOp::SetPitch(_)
+        | DecodedOp::Unknown(_) => return None,
+    };
+
+    Some(text)
+}
+
+/// Addresses of opcodes reachable from the entry point, following jumps,
+/// calls and both outcomes of skips. BNNN jumps are not followed, since
+/// their target depends on V0.
+fn reachable(rom: &[u8], origin: u16) -> BTreeSet<u16> {
+    let end = origin as usize + rom.len();
+    let opcode_at = |address: u16| {
+        let offset = (address - origin) as usize;
+        u16::from_be_bytes([rom[offset], rom[offset + 1]])
+    };
+
+    let mut code = BTreeSet::new();
+    let mut worklist = vec![origin];
+    while let Some(address) = worklist.pop() {
+        if address < origin || address as usize + 2 > end || !code.insert(address) {
+            continue;
+        }
+
+        let next = address + 2;
+        match DecodedOp::decode(opcode_at(address), false) {
+            DecodedOp::SelectPlanes(_)
+            | DecodedOp::LoadAudioPattern
+            | DecodedOp::SetPitch(_)
+            | DecodedOp::Unknown(_) => {
+                code.remove(&address);
+            }
+            DecodedOp::Return | DecodedOp::JumpOffset(_) => {}
+            DecodedOp::Jump(target) => worklist.push(target),
+            DecodedOp::Call(target) => worklist.extend([target, next]),
+            DecodedOp::SkipIfEqual { .. }
+            | DecodedOp::SkipIfNotEqual { .. }
+            | DecodedOp::SkipIfRegistersEqual { .. }
+            | DecodedOp::SkipIfRegistersNotEqual { .. }
+            | DecodedOp::SkipIfKey(_)
+            | DecodedOp::SkipIfNotKey(_) => worklist.extend([next, next + 2]),
+            _ => worklist.push(next),
+        }
+    }
+
+    code
+}
+
+/// Lists the ROM loaded at `origin` with reachable opcodes disassembled and
+/// everything else as `DB`
+pub fn analyze(rom: &[u8], origin: u16) -> Result<String> {
+    if origin >= 0x1000 || rom.len() > 0x1000 - origin as usize {
+        bail!("ROM is too large: {} bytes at {:#05X}", rom.len(), origin);
+    }
+    let code = reachable(rom, origin);
+    let mut listing = String::new();
+
+    let mut offset = 0;
+    while offset < rom.len() {
+        let address = origin + offset as u16;
+        if code.contains(&address) {
+            let opcode = u16::from_be_bytes([rom[offset], rom[offset + 1]]);
+            let text = mnemonic(opcode).unwrap_or_default();
+            let _ = writeln!(listing, "{:03X}: {:04X}  {}", address, opcode, text);
+            offset += 2;
+        } else {
+            let _ = writeln!(
+                listing,
+                "{:03X}: {:02X}    DB {:#04X}",
+                address, rom[offset], rom[offset]
+            );
+            offset += 1;
+        }
+    }
+
+    Ok(listing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn listing_starts_at_the_origin_and_skips_data() {
+        // Jump over a data byte, then loop
+        let rom = [0x13, 0x03, 0xAB, 0x00, 0xE0, 0x13, 0x03];
+        let listing = analyze(&rom, 0x300).unwrap();
+        assert_eq!(
+            listing,
+            "300: 1303  JP 0x303\n\
+             302: AB    DB 0xAB\n\
+             303: 00E0  CLS\n\
+             305: 1303  JP 0x303\n"
+        );
+    }
+
+    #[test]
+    fn mnemonics_follow_the_decoder() {
+        assert_eq!(mnemonic(0x00E0).as_deref(), Some("CLS"));
+        assert_eq!(mnemonic(0x01E0).as_deref(), Some("SYS 0x1E0"));
+        assert_eq!(mnemonic(0x8AB6).as_deref(), Some("SHR VA, VB"));
+        assert_eq!(mnemonic(0x8AB8), None);
+        assert_eq!(mnemonic(0xF201), None);
+    }
+
+    #[test]
+    fn roms_past_the_end_of_memory_are_rejected() {
+        assert!(analyze(&[0; 0xE00], 0x200).is_ok());
+        assert!(analyze(&[0; 0xE01], 0x200).is_err());
+        assert!(analyze(&vec![0; 0x10000], 0x200).is_err());
+        assert!(analyze(&[0; 2], 0x1000).is_err());
+    }
+}
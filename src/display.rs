@@ -0,0 +1,213 @@
+use anyhow::{anyhow, Result};
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, WindowCanvas};
+use sdl2::VideoSubsystem;
+
+use crate::canvas::{self, Canvas};
+use crate::toast::{self, Toast};
+use crate::{HEIGHT, WIDTH};
+
+/// Shows the CHIP-8 canvas in a window or terminal, along with overlays such
+/// as toasts, stats and collision highlights
+pub trait Display {
+    /// Draws one frame from both bit-planes
+    fn present(&mut self, canvas: &[Canvas; 2]) -> Result<()>;
+
+    /// Sets how many host pixels one CHIP-8 pixel covers in each direction
+    fn set_scale(&mut self, scale: u32) -> Result<()>;
+
+    /// Overlays a short message for the next `duration_frames` frames
+    fn show_toast(&mut self, text: &str, duration_frames: u32);
+
+    /// Shows `pixels` in a distinct color on the next presented frame
+    fn highlight(&mut self, pixels: &Canvas);
+
+    /// Overlays performance counters until replaced, an empty text hides them
+    fn show_stats(&mut self, text: &str);
+
+    /// Darkens the gap between CHIP-8 pixel rows for a CRT look
+    fn set_scanlines(&mut self, scanlines: bool);
+}
+
+/// Colors for each color index, classic programs only use the first two
+const DEFAULT_PALETTE: [Color; 4] = [
+    Color::WHITE,
+    Color::BLACK,
+    Color::RGB(0xAA, 0xAA, 0xAA),
+    Color::RGB(0x55, 0x55, 0x55),
+];
+
+/// Color of highlighted pixels, e.g. those turned off by collisions
+const HIGHLIGHT_COLOR: Color = Color::RED;
+
+/// Scanlines are drawn over the bottom host row of every CHIP-8 row, so
+/// smaller scales would darken half the picture or more
+const MIN_SCANLINE_SCALE: u32 = 3;
+const SCANLINE_COLOR: Color = Color::RGBA(0, 0, 0, 0x80);
+
+/// Brightness lost per frame by a pixel that was turned off
+const FADE_STEP: u8 = 64;
+
+pub struct SdlDisplay {
+    canvas: WindowCanvas,
+    scale: u32,
+    /// Per-pixel brightness when fading is enabled, the canvas stays
+    /// authoritative for what is actually set
+    brightness: Option<Box<[[u8; WIDTH]; HEIGHT]>>,
+    palette: [Color; 4],
+    toast: Toast,
+    stats: String,
+    highlight: Vec<Rect>,
+    scanlines: bool,
+}
+
+impl SdlDisplay {
+    /// The window starts at scale 1, use [`Display::set_scale`] to resize it
+    pub fn new(video_subsystem: &VideoSubsystem) -> Result<Self> {
+        let window = video_subsystem
+            .window("CHIP-8", WIDTH as u32, HEIGHT as u32)
+            .position_centered()
+            .opengl()
+            .build()?;
+
+        let mut canvas = window.into_canvas().accelerated().present_vsync().build()?;
+        // Only the scanlines are translucent, opaque colors draw as before
+        canvas.set_blend_mode(BlendMode::Blend);
+
+        Ok(Self {
+            canvas,
+            scale: 1,
+            brightness: None,
+            palette: DEFAULT_PALETTE,
+            toast: Toast::default(),
+            stats: String::new(),
+            highlight: Vec::new(),
+            scanlines: false,
+        })
+    }
+
+    /// Sets the colors of unset pixels and of pixels set in the first plane
+    pub fn set_colors(&mut self, foreground: Color, background: Color) {
+        self.palette[0] = background;
+        self.palette[1] = foreground;
+    }
+
+    /// Makes pixels that turn off fade out over a few frames to hide flicker
+    pub fn set_fade(&mut self, fade: bool) {
+        self.brightness = fade.then(|| Box::new([[0; WIDTH]; HEIGHT]));
+    }
+}
+
+impl Display for SdlDisplay {
+    fn present(&mut self, canvas: &[Canvas; 2]) -> Result<()> {
+        let scale = self.scale;
+        let palette = self.palette.map(|color| [color.r, color.g, color.b, 0xFF]);
+        // Rendered at one host pixel per CHIP-8 pixel, the copy below scales it
+        let mut pixels = canvas::render_rgba(canvas, 1, &palette);
+
+        if let Some(brightness) = &mut self.brightness {
+            let (on, off) = (palette[1], palette[0]);
+            for ((x, y, low), (_, _, high)) in canvas[0].iter_pixels().zip(canvas[1].iter_pixels())
+            {
+                let pixel = &mut brightness[y][x];
+                if low || high {
+                    *pixel = u8::MAX;
+                    continue;
+                }
+                *pixel = pixel.saturating_sub(FADE_STEP);
+                if *pixel == 0 {
+                    continue;
+                }
+
+                // Blend from the foreground (fully lit) towards the background
+                let weight = ((u8::MAX - *pixel) / FADE_STEP * FADE_STEP) as u32;
+                let offset = (y * WIDTH + x) * 4;
+                let rgb = &mut pixels[offset..offset + 3];
+                for ((channel, on), off) in rgb.iter_mut().zip(on).zip(off) {
+                    let blended = on as u32 * (u8::MAX as u32 - weight) + off as u32 * weight;
+                    *channel = (blended / u8::MAX as u32) as u8;
+                }
+            }
+        }
+
+        let texture_creator = self.canvas.texture_creator();
+        let mut texture = texture_creator.create_texture_streaming(
+            PixelFormatEnum::RGBA32,
+            WIDTH as u32,
+            HEIGHT as u32,
+        )?;
+        texture.update(None, &pixels, WIDTH * 4)?;
+        self.canvas
+            .copy(&texture, None, None)
+            .map_err(|err| anyhow!(err))?;
+
+        self.canvas.set_draw_color(HIGHLIGHT_COLOR);
+        self.canvas
+            .fill_rects(&self.highlight)
+            .map_err(|err| anyhow!(err))?;
+        self.highlight.clear();
+
+        // Only the scaled output is shaded, the canvas bits stay untouched
+        if self.scanlines && scale >= MIN_SCANLINE_SCALE {
+            let width = scale * WIDTH as u32;
+            let lines: Vec<_> = (1..=HEIGHT as u32)
+                .map(|row| Rect::new(0, (row * scale - 1) as i32, width, 1))
+                .collect();
+            self.canvas.set_draw_color(SCANLINE_COLOR);
+            self.canvas.fill_rects(&lines).map_err(|err| anyhow!(err))?;
+        }
+
+        let pixel = (scale / 3).max(1);
+        if !self.stats.is_empty() {
+            toast::render_bar(&mut self.canvas, &self.stats, pixel, 0)?;
+        }
+        self.toast.render(&mut self.canvas, pixel)?;
+        self.canvas.present();
+        self.toast.tick();
+
+        Ok(())
+    }
+
+    fn set_scale(&mut self, scale: u32) -> Result<()> {
+        self.scale = scale;
+        self.canvas
+            .window_mut()
+            .set_size(scale * WIDTH as u32, scale * HEIGHT as u32)?;
+        self.canvas.window_mut().set_position(
+            sdl2::video::WindowPos::Centered,
+            sdl2::video::WindowPos::Centered,
+        );
+        Ok(())
+    }
+
+    fn show_toast(&mut self, text: &str, duration_frames: u32) {
+        self.toast.show_toast(text, duration_frames);
+    }
+
+    fn highlight(&mut self, pixels: &Canvas) {
+        let scale = self.scale;
+        self.highlight.extend(
+            pixels
+                .iter_pixels()
+                .filter(|&(_, _, on)| on)
+                .map(|(x, y, _)| {
+                    Rect::new(
+                        (scale as usize * x) as i32,
+                        (scale as usize * y) as i32,
+                        scale,
+                        scale,
+                    )
+                }),
+        );
+    }
+
+    fn show_stats(&mut self, text: &str) {
+        self.stats.clear();
+        self.stats.push_str(text);
+    }
+
+    fn set_scanlines(&mut self, scanlines: bool) {
+        self.scanlines = scanlines;
+    }
+}
@@ -2,9 +2,61 @@ use std::path::PathBuf;
 
 xflags::xflags! {
     cmd main {
+        optional --config config: PathBuf
         optional -f, --frequency frequency: u32
         optional -b, --benchmark
         optional -c, --count count: u32
+        optional --max-runtime secs: u64
+        optional --rom-readonly
+        optional --strict
+        optional --xo-chip
+        optional --stack-depth depth: usize
+        optional --stack-wrap
+        optional --jump-with-vx
+        optional --logic-resets-vf
+        optional --load-store-increments-i mode: String
+        optional --wrap-sprites
+        optional --i-overflow-sets-vf
+        optional --halt-on-top-return
+        optional --halt-on-idle
+        optional --profile-waits
+        optional --profile
+        optional --time-draws
+        optional --debug-port
+        optional --trace path: PathBuf
+        optional --coverage path: PathBuf
+        optional --load-address address: String
+        optional --font font: PathBuf
+        optional --font-address address: String
+        repeated --load segment: String
+        optional --dump-state path: PathBuf
+        optional --memdump path: PathBuf
+        optional --no-audio
+        optional --volume volume: f32
+        optional -s, --scale scale: u32
+        optional --fade
+        optional --scanlines
+        optional --theme theme: String
+        optional --fg color: String
+        optional --bg color: String
+        optional --show-stats
+        optional --highlight-collisions
+        optional --tui
+        optional --frame-step
+        optional --console
+        optional --seed seed: u64
+        optional --frame-deterministic-rng
+        repeated --watch watch: String
+        optional --state-config
+        optional --rewind frames: u32
+        repeated --pad mapping: String
+        optional --key-repeat millis: u32
+        optional --record-input path: PathBuf
+        optional --play-input path: PathBuf
+        optional --assemble
+        optional -o, --output output: PathBuf
+        optional --analyze
+        optional --verify
         required path: PathBuf
     }
 }
@@ -0,0 +1,60 @@
+use crate::virtual_machine::VirtualMachine;
+
+/// Steps executed per input
+const STEPS: u32 = 10_000;
+/// The pressed key changes this often
+const KEY_INTERVAL: u32 = 100;
+
+/// The first 8 bytes of `input` seed CXNN, the quirks and the key presses,
+/// the rest is the ROM, so a saved input reproduces its crash exactly.
+/// Errors are the graceful outcome, only panics count as crashes.
+///
+/// Out-of-bounds accesses only panic in debug builds or with the
+/// `safe-memory` feature, release builds without it can't detect them.
+pub fn run(input: &[u8]) {
+    let Some((seed, rom)) = input.split_first_chunk::<8>() else {
+        return;
+    };
+    let seed = u64::from_le_bytes(*seed);
+
+    let Ok(mut machine) = VirtualMachine::from_bytes(rom) else {
+        return;
+    };
+    machine.quiet = true;
+    machine.seed_rng(seed);
+    let mut rng = fastrand::Rng::with_seed(seed);
+    machine.xo_chip = rng.bool();
+    machine.strict = rng.bool();
+    machine.wrap_sprites = rng.bool();
+    machine.stack_wrap = rng.bool();
+
+    for step in 0..STEPS {
+        if step % KEY_INTERVAL == 0 {
+            machine.pressed_key = rng.bool().then(|| rng.u8(..0x10));
+        }
+        if machine.step().is_err() || machine.is_halted() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn random_inputs_do_not_panic() {
+        let mut rng = fastrand::Rng::with_seed(0);
+        for _ in 0..50 {
+            let len = rng.usize(8..0x200);
+            let input: Vec<u8> = std::iter::repeat_with(|| rng.u8(..)).take(len).collect();
+            run(&input);
+        }
+    }
+
+    #[test]
+    fn short_inputs_are_ignored() {
+        run(&[]);
+        run(&[1, 2, 3]);
+    }
+}
@@ -0,0 +1,51 @@
+use anyhow::{bail, Context, Result};
+use sdl2::controller::Button;
+
+/// Default layout mirrors the keyboard: the d-pad acts as W/A/S/D
+const DEFAULT_MAPPING: [(Button, u8); 8] = [
+    (Button::DPadUp, 0x5),
+    (Button::DPadLeft, 0x7),
+    (Button::DPadDown, 0x8),
+    (Button::DPadRight, 0x9),
+    (Button::A, 0x6),
+    (Button::B, 0x4),
+    (Button::X, 0x1),
+    (Button::Y, 0x2),
+];
+
+/// Maps game controller buttons to CHIP-8 keys
+pub struct GamepadMapping {
+    keys: Vec<(Button, u8)>,
+}
+
+impl GamepadMapping {
+    /// Overrides have the form `button=KEY`, e.g. `a=5` or `dpup=2`, using
+    /// SDL button names and a hexadecimal CHIP-8 key
+    pub fn new(overrides: &[String]) -> Result<Self> {
+        let mut keys = DEFAULT_MAPPING.to_vec();
+
+        for mapping in overrides {
+            let Some((button, key)) = mapping.split_once('=') else {
+                bail!("Invalid gamepad mapping: {} (expected button=KEY)", mapping);
+            };
+            let button = Button::from_string(button)
+                .with_context(|| format!("Unknown gamepad button: {}", button))?;
+            let key = u8::from_str_radix(key, 16)
+                .ok()
+                .filter(|&key| key < 0x10)
+                .with_context(|| format!("Invalid CHIP-8 key: {}", key))?;
+
+            keys.retain(|&(mapped, _)| mapped != button);
+            keys.push((button, key));
+        }
+
+        Ok(Self { keys })
+    }
+
+    pub fn key(&self, button: Button) -> Option<u8> {
+        self.keys
+            .iter()
+            .find(|&&(mapped, _)| mapped == button)
+            .map(|&(_, key)| key)
+    }
+}
@@ -0,0 +1,102 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+
+/// Writes key changes for `--record-input`, one per line as
+/// `FRAME KEY down|up` with a decimal frame and a hexadecimal CHIP-8 key
+pub struct InputRecorder {
+    out: BufWriter<File>,
+    key: Option<u8>,
+}
+
+impl InputRecorder {
+    pub fn create(path: &Path) -> Result<Self> {
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create input recording: {:?}", path))?;
+        Ok(Self {
+            out: BufWriter::new(file),
+            key: None,
+        })
+    }
+
+    /// Records how the pressed key changed since the previous frame
+    pub fn record(&mut self, frame: u64, key: Option<u8>) -> Result<()> {
+        if key == self.key {
+            return Ok(());
+        }
+        if let Some(released) = self.key {
+            writeln!(self.out, "{} {:X} up", frame, released)?;
+        }
+        if let Some(pressed) = key {
+            writeln!(self.out, "{} {:X} down", frame, pressed)?;
+        }
+        self.key = key;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.out.flush().context("Failed to write input recording")
+    }
+}
+
+/// Replays a `--record-input` file for `--play-input`
+pub struct InputPlayer {
+    events: VecDeque<(u64, u8, bool)>,
+    key: Option<u8>,
+}
+
+impl InputPlayer {
+    /// Blank lines and lines starting with `#` are ignored
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read input recording: {:?}", path))?;
+
+        let mut events = VecDeque::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let parts: Vec<_> = line.split_whitespace().collect();
+            let event = match parts[..] {
+                [frame, key, state @ ("down" | "up")] => frame
+                    .parse()
+                    .ok()
+                    .zip(u8::from_str_radix(key, 16).ok().filter(|&key| key < 0x10))
+                    .map(|(frame, key)| (frame, key, state == "down")),
+                _ => None,
+            };
+            let Some(event) = event else {
+                bail!(
+                    "Invalid input event on line {}: {} (expected FRAME KEY down|up)",
+                    number + 1,
+                    line
+                );
+            };
+            events.push_back(event);
+        }
+
+        Ok(Self { events, key: None })
+    }
+
+    /// Key held during `frame`, frames must be queried in increasing order
+    pub fn key_at(&mut self, frame: u64) -> Option<u8> {
+        while let Some(&(at, key, down)) = self.events.front() {
+            if at > frame {
+                break;
+            }
+            self.events.pop_front();
+            if down {
+                self.key = Some(key);
+            } else if self.key == Some(key) {
+                self.key = None;
+            }
+        }
+
+        self.key
+    }
+}
@@ -0,0 +1,9 @@
+pub mod canvas;
+pub mod characters;
+pub mod decode;
+pub mod fuzz;
+pub mod virtual_machine;
+
+/// Size of the CHIP-8 display in pixels
+pub const WIDTH: usize = 64;
+pub const HEIGHT: usize = 32;
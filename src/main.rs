@@ -1,40 +1,249 @@
+mod assemble;
 mod audio;
-mod characters;
+mod clipboard;
+mod config;
+mod console;
+mod disassemble;
+mod display;
 mod flags;
-mod virtual_machine;
+mod gamepad;
+mod input_log;
+mod rewind;
+mod save_state;
+mod toast;
+mod tui;
+mod watchpoints;
 
-use anyhow::{anyhow, Result};
-use audio::SquareWave;
-use sdl2::audio::AudioSpecDesired;
+use anyhow::{anyhow, bail, Context, Result};
+use audio::{AudioBackend, BeepLatch, NullAudio, SdlAudio};
+use chip_8::{canvas, characters, decode, virtual_machine, HEIGHT, WIDTH};
+use config::Config;
+use decode::DecodedOp;
+use display::{Display, SdlDisplay};
+use gamepad::GamepadMapping;
+use input_log::{InputPlayer, InputRecorder};
+use rewind::Rewind;
+use save_state::RunConfig;
 use sdl2::event::Event;
 use sdl2::keyboard::{Keycode, Scancode};
 use sdl2::pixels::Color;
-use sdl2::rect::Rect;
+use std::io::{Read, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::{Duration, Instant};
-use virtual_machine::VirtualMachine;
+use virtual_machine::{rom_problem, IndexIncrement, Quirks, VirtualMachine, DEFAULT_LOAD_ADDRESS};
+use watchpoints::Watchpoints;
 
-const PIXEL_SIZE: usize = 12;
-const WIDTH: usize = 64;
-const HEIGHT: usize = 32;
-const WINDOW_Y: u32 = (PIXEL_SIZE * HEIGHT) as u32;
-const WINDOW_X: u32 = (PIXEL_SIZE * WIDTH) as u32;
+const DEFAULT_SCALE: u32 = 12;
+const MAX_SCALE: u32 = 64;
 const REFRESH_RATE: u32 = 60;
 const FRAME_TIME: Duration = Duration::from_nanos(1_000_000_000 / REFRESH_RATE as u64);
 const CLOCK_HZ: u32 = 1000;
+const MAX_CATCH_UP_FRAMES: u32 = 4;
+const TOAST_FRAMES: u32 = 2 * REFRESH_RATE;
+const TURBO_FACTOR: u32 = 4;
+/// `[` and `]` change the frequency by this much, between one instruction
+/// per frame and MAX_FREQUENCY
+const FREQUENCY_STEP: u32 = 120;
+const MAX_FREQUENCY: u32 = 60_000;
+const DEFAULT_VOLUME: f32 = 0.2;
+
+/// Set by the Ctrl-C handler, checked by every run loop
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
 
 fn main() -> Result<()> {
     let flags = flags::Main::from_env_or_exit();
 
+    if flags.assemble {
+        let source = std::fs::read_to_string(&flags.path)
+            .with_context(|| format!("Failed to read source: {:?}", flags.path))?;
+        let rom = assemble::assemble(&source)?;
+        let output = flags
+            .output
+            .unwrap_or_else(|| flags.path.with_extension("ch8"));
+        std::fs::write(&output, rom)
+            .with_context(|| format!("Failed to write ROM: {:?}", output))?;
+        return Ok(());
+    }
+
+    let config = match &flags.config {
+        Some(path) => Config::load(path)?,
+        None => Config::default(),
+    };
+    let quirks = &config.quirks;
+
+    let load_address = match &flags.load_address {
+        Some(address) => parse_hex(address, "load address")?,
+        None => DEFAULT_LOAD_ADDRESS,
+    };
+    if flags.analyze {
+        let rom = read_rom(&flags.path)?;
+        print!("{}", disassemble::analyze(&rom, load_address)?);
+        return Ok(());
+    }
+    if flags.console && flags.path == Path::new("-") {
+        bail!("--console reads stdin, so the ROM can't be read from it too");
+    }
+    let rom = read_rom(&flags.path)?;
+    // In strict mode building the machine fails with the same problem instead
+    if !(flags.strict || quirks.strict) {
+        if let Some(problem) = rom_problem(rom.len()) {
+            eprintln!("Warning: {}", problem);
+        }
+    }
+
+    if flags.verify {
+        let mut machine = VirtualMachine::from_bytes_at(&rom, load_address)?;
+        machine.xo_chip = flags.xo_chip || quirks.xo_chip;
+        return verify(&machine);
+    }
+
+    // Flags add to the quirks from the config file
+    let load_store_increments_i = match flags
+        .load_store_increments_i
+        .as_ref()
+        .or(quirks.load_store_increments_i.as_ref())
+    {
+        Some(increment) => IndexIncrement::parse(increment)?,
+        None => IndexIncrement::None,
+    };
+    let quirks = Quirks {
+        rom_readonly: flags.rom_readonly || quirks.rom_readonly,
+        strict: flags.strict || quirks.strict,
+        xo_chip: flags.xo_chip || quirks.xo_chip,
+        stack_wrap: flags.stack_wrap || quirks.stack_wrap,
+        jump_with_vx: flags.jump_with_vx || quirks.jump_with_vx,
+        logic_resets_vf: flags.logic_resets_vf || quirks.logic_resets_vf,
+        load_store_increments_i,
+        wrap_sprites: flags.wrap_sprites || quirks.wrap_sprites,
+        i_overflow_sets_vf: flags.i_overflow_sets_vf || quirks.i_overflow_sets_vf,
+        halt_on_idle: flags.halt_on_idle || quirks.halt_on_idle,
+        halt_on_top_return: flags.halt_on_top_return || quirks.halt_on_top_return,
+    };
+    let mut builder = VirtualMachine::builder()
+        .quirks(quirks)
+        .load_address(load_address);
+    if let Some(depth) = flags.stack_depth {
+        builder = builder.stack_depth(depth);
+    }
+    if let Some(path) = &flags.font {
+        let font =
+            std::fs::read(path).with_context(|| format!("Failed to read font: {:?}", path))?;
+        let font: [u8; 80] = font
+            .try_into()
+            .map_err(|font: Vec<u8>| anyhow!("Font must be 80 bytes, got {}", font.len()))?;
+        builder = builder.font(font);
+    }
+    if let Some(address) = &flags.font_address {
+        builder = builder.font_address(parse_hex(address, "font address")?);
+    }
+    // A fixed seed makes CXNN results reproducible between runs
+    if let Some(seed) = flags.seed {
+        builder = builder.seed(seed);
+    }
+
+    let mut machine = builder.build_from_bytes(&rom)?;
+    if flags.frame_deterministic_rng {
+        machine.frame_rng_seed = Some(flags.seed.unwrap_or(0));
+    }
+    machine.profile_waits = flags.profile_waits;
+    machine.debug_port = flags.debug_port;
+    if flags.highlight_collisions {
+        machine.enable_collision_highlight();
+    }
+    if let Some(path) = &flags.trace {
+        let file = std::fs::File::create(path)
+            .with_context(|| format!("Failed to create trace: {:?}", path))?;
+        machine.set_trace(Box::new(file));
+    }
+    if let Some(path) = &flags.coverage {
+        let file = std::fs::File::create(path)
+            .with_context(|| format!("Failed to create coverage file: {:?}", path))?;
+        let mut out = std::io::BufWriter::new(file);
+        let mut executed = vec![false; 0x1000];
+        // Each address is listed once, when it first executes
+        machine.set_pre_step_hook(Box::new(move |pc, opcode| {
+            if !std::mem::replace(&mut executed[pc as usize], true) {
+                let _ = writeln!(out, "{:03X} {:04X}", pc, opcode);
+            }
+        }));
+    }
+    if flags.profile {
+        machine.enable_opcode_profile();
+    }
+    if flags.time_draws {
+        machine.enable_draw_timing();
+    }
+    load_segments(&mut machine, &flags.load)?;
+
+    if flags.dump_state.is_some() && !cfg!(feature = "serde") {
+        bail!("--dump-state requires the serde feature");
+    }
+
+    // Ctrl-C stops the run like closing the window, so the exit reports
+    // are printed and the trace is flushed
+    ctrlc::set_handler(|| INTERRUPTED.store(true, Ordering::Relaxed))?;
+
+    // The machine state makes crash reports far more useful than the bare panic message
+    match panic::catch_unwind(AssertUnwindSafe(|| run(&flags, &config, &mut machine))) {
+        Ok(result) => result,
+        Err(payload) => {
+            eprintln!("{}", machine.dump_debug_state());
+            panic::resume_unwind(payload)
+        }
+    }
+}
+
+fn run(flags: &flags::Main, config: &Config, machine: &mut VirtualMachine) -> Result<()> {
+    let mut watchpoints = Watchpoints::new(&flags.watch, machine)?;
+
+    // `--count` is in millions of instructions
+    let instruction_limit = flags.count.map(|millions| millions as u64 * 1_000_000);
+    let start = Instant::now();
+    // `--max-runtime` stops every mode cleanly after that many seconds
+    let deadline = flags
+        .max_runtime
+        .map(|secs| start + Duration::from_secs(secs));
+    let timed_out = || {
+        let timed_out = deadline.is_some_and(|deadline| Instant::now() >= deadline);
+        if timed_out {
+            eprintln!("Stopped after the maximum runtime");
+        }
+        timed_out
+    };
+
+    // Benchmarks run headless as fast as possible, without timers or input
     if flags.benchmark {
-        let mut machine = VirtualMachine::new(&flags.path)?;
-        let start = Instant::now();
-        let millions = flags.count.unwrap_or(100);
-        for _ in 0..(millions * 1_000_000) {
+        let limit = instruction_limit.unwrap_or(100_000_000);
+        while machine.instruction_count() < limit
+            && !machine.is_halted()
+            && !INTERRUPTED.load(Ordering::Relaxed)
+        {
             machine.execute_opcode()?;
+            // Reading the clock costs more than an opcode, so check it rarely
+            if machine.instruction_count().is_multiple_of(0x10000) && timed_out() {
+                break;
+            }
         }
-        let elapsed = start.elapsed();
-        println!("{:.2}", millions as f64 / elapsed.as_secs_f64());
-        return Ok(());
+        print_speed(machine, start);
+        return report_exit(flags, machine);
+    }
+
+    let frequency = flags.frequency.or(config.frequency).unwrap_or(CLOCK_HZ);
+    let mut instructions_per_frame = frequency / REFRESH_RATE;
+
+    if flags.tui {
+        tui::run(machine, instructions_per_frame, instruction_limit, deadline)?;
+        if instruction_limit.is_some_and(|limit| machine.instruction_count() >= limit) {
+            print_speed(machine, start);
+        }
+        return report_exit(flags, machine);
+    }
+
+    let scale = flags.scale.or(config.scale).unwrap_or(DEFAULT_SCALE);
+    if scale == 0 || scale > MAX_SCALE {
+        bail!("Scale must be between 1 and {}, got {}", MAX_SCALE, scale);
     }
 
     // Set default video driver to wayland
@@ -42,84 +251,257 @@ fn main() -> Result<()> {
 
     let sdl_context = sdl2::init().map_err(|err| anyhow!(err))?;
     let video_subsystem = sdl_context.video().map_err(|err| anyhow!(err))?;
-    let audio_subsystem = sdl_context.audio().map_err(|err| anyhow!(err))?;
+    let controller_subsystem = sdl_context.game_controller().map_err(|err| anyhow!(err))?;
 
-    let window = video_subsystem
-        .window("CHIP-8", WINDOW_X, WINDOW_Y)
-        .position_centered()
-        .opengl()
-        .build()?;
+    let mut sdl_display = SdlDisplay::new(&video_subsystem)?;
+    sdl_display.set_fade(flags.fade);
+    // `--fg` and `--bg` override the theme, which overrides the config file
+    let (foreground, background) = config.colors(
+        flags.theme.as_deref(),
+        flags.fg.as_deref(),
+        flags.bg.as_deref(),
+    )?;
+    sdl_display.set_colors(Color::from(foreground), Color::from(background));
+    let mut display: Box<dyn Display> = Box::new(sdl_display);
+    display.set_scale(scale)?;
+    // F2 toggles the scanlines
+    let mut scanlines = flags.scanlines;
+    display.set_scanlines(scanlines);
 
-    // Audio configuration
-
-    let desired_spec = AudioSpecDesired {
-        freq: Some(44_100),
-        channels: Some(1),
-        samples: None,
+    // Audio configuration, running silently if no device can be opened
+    let mut audio: Box<dyn AudioBackend> = if flags.no_audio {
+        Box::new(NullAudio)
+    } else {
+        let sdl_audio = sdl_context
+            .audio()
+            .map_err(|err| anyhow!(err))
+            .and_then(|audio_subsystem| SdlAudio::new(&audio_subsystem, DEFAULT_VOLUME));
+        match sdl_audio {
+            Ok(sdl_audio) => Box::new(sdl_audio),
+            Err(err) => {
+                eprintln!("Failed to open audio, continuing without sound: {:#}", err);
+                Box::new(NullAudio)
+            }
+        }
     };
+    if let Some(volume) = flags.volume {
+        audio.set_volume(volume.clamp(0.0, 1.0));
+    }
+
+    // Window interaction
+    let mut event_pump = sdl_context.event_pump().map_err(|err| anyhow!(err))?;
 
-    let device = audio_subsystem
-        .open_playback(None, &desired_spec, |spec| SquareWave {
-            phase_inc: 200.0 / spec.freq as f32,
-            phase: 0.0,
-            volume: 0.2,
+    let mut beep = BeepLatch::default();
+
+    // Config bindings add to the default keyboard layout
+    let key_bindings = config
+        .keys
+        .iter()
+        .map(|(key, name)| {
+            let key = u8::from_str_radix(key, 16)
+                .ok()
+                .filter(|&key| key < 0x10)
+                .with_context(|| format!("Invalid CHIP-8 key in config: {}", key))?;
+            let scancode = Scancode::from_name(name)
+                .with_context(|| format!("Unknown key name in config: {}", name))?;
+            Ok((scancode, key))
         })
-        .map_err(|err| anyhow!(err))?;
+        .collect::<Result<Vec<_>>>()?;
 
-    // Window interaction
-    let mut canvas = window.into_canvas().accelerated().present_vsync().build()?;
+    // Controllers are opened as they get connected, including ones present at startup
+    let pad_mapping = GamepadMapping::new(&flags.pad)?;
+    let mut controllers = Vec::new();
 
-    let mut event_pump = sdl_context.event_pump().map_err(|err| anyhow!(err))?;
+    // In frame-step mode the machine stays paused until Return is pressed,
+    // then runs exactly one frame. A triggered watchpoint switches to it.
+    let mut frame_step = flags.frame_step;
+    let mut step_requested = false;
 
-    // Our virtual machine
-    let mut machine = VirtualMachine::new(&flags.path)?;
-    let frequency = flags.frequency.unwrap_or(CLOCK_HZ);
-    let instructions_per_frame = frequency / REFRESH_RATE;
-    device.resume();
+    // F5 saves and F9 loads a state stored next to the ROM
+    let state_path = flags.path.with_extension("state");
 
-    let mut rects = Vec::with_capacity(WIDTH * HEIGHT);
+    // Holding Backspace steps back through the last `--rewind` frames
+    let mut rewind = Rewind::new(flags.rewind.unwrap_or(0));
+    let mut rewinding = false;
 
-    'main: loop {
-        let now = Instant::now();
-        rects.clear();
+    // Space pauses, the window keeps redrawing and handling input. While
+    // paused, N executes a single instruction.
+    let mut paused = false;
 
-        machine.delay_timer = machine.delay_timer.saturating_sub(1);
-        machine.sound_timer = machine.sound_timer.saturating_sub(1);
+    // Holding Tab multiplies the instruction rate, timers keep running at 60Hz
+    let mut turbo = false;
 
-        for _ in 0..instructions_per_frame {
-            machine.execute_opcode()?;
+    // `--key-repeat` releases a held key for one frame at a fixed interval,
+    // so games that wait for a press and a release see it again. A key held
+    // during FX0A is left alone, FX0A still needs a real release.
+    let key_repeat = flags
+        .key_repeat
+        .map(|millis| Duration::from_millis(millis as u64));
+    let mut repeat_key: Option<(u8, Instant)> = None;
+
+    // `--record-input` logs the key held in every emulated frame and
+    // `--play-input` replaces live input with such a log
+    let mut recorder = flags
+        .record_input
+        .as_deref()
+        .map(InputRecorder::create)
+        .transpose()?;
+    let mut player = flags
+        .play_input
+        .as_deref()
+        .map(InputPlayer::load)
+        .transpose()?;
+    let mut input_frame = 0;
+
+    // `--console` takes commands that patch memory and registers on stdin,
+    // best combined with pausing and single-stepping
+    let console = flags.console.then(console::spawn);
+
+    // F7 cycles through the bundled fonts
+    let mut font_index = 0;
+
+    // Timing model: emulation runs in fixed 60Hz steps driven by wall-clock
+    // time. Time elapsed since the previous iteration goes into `lag`, and
+    // every whole FRAME_TIME in it is one emulated frame: the timers tick once
+    // and `instructions_per_frame` opcodes run. Presenting happens once per
+    // iteration, so a slow or vsync-blocked present only delays rendering
+    // while emulated time catches up on the next iteration. Input is polled
+    // until the next frame is due.
+    let mut lag = Duration::ZERO;
+    let mut last_update = Instant::now();
+
+    // `--show-stats` refreshes the overlay once per second
+    let mut stats_since = Instant::now();
+    let mut stats_frames = 0;
+    let mut stats_instructions = machine.instruction_count();
+
+    'main: loop {
+        if INTERRUPTED.load(Ordering::Relaxed) || timed_out() {
+            break;
         }
 
-        if machine.sound_timer > 0 {
-            device.resume()
-        } else {
-            device.pause();
+        if let Some(console) = &console {
+            for line in console.try_iter() {
+                match console::Command::parse(&line).and_then(|command| command.apply(machine)) {
+                    Ok(()) => println!("OK"),
+                    Err(err) => eprintln!("{:#}", err),
+                }
+            }
         }
 
-        canvas.set_draw_color(Color::WHITE);
-        canvas.clear();
+        let now = Instant::now();
+        // After a long stall (e.g. a dragged window) skip ahead instead of
+        // running many frames at once
+        lag = (lag + (now - last_update)).min(MAX_CATCH_UP_FRAMES * FRAME_TIME);
+        last_update = now;
+
+        while lag >= FRAME_TIME {
+            lag -= FRAME_TIME;
+
+            let running = !paused && (!frame_step || step_requested);
+            if rewinding {
+                rewind.pop(machine)?;
+            } else if running {
+                rewind.push(machine);
+
+                if let Some(player) = &mut player {
+                    machine.pressed_key = player.key_at(input_frame);
+                }
+
+                let mut repeated_key = None;
+                if let Some(interval) = key_repeat {
+                    match (machine.pressed_key, repeat_key) {
+                        (Some(key), Some((held, since))) if key == held => {
+                            if since.elapsed() >= interval && !machine.is_waiting_for_release() {
+                                machine.pressed_key = None;
+                                repeated_key = Some(key);
+                                repeat_key = Some((key, Instant::now()));
+                            }
+                        }
+                        (Some(key), _) => repeat_key = Some((key, Instant::now())),
+                        (None, _) => repeat_key = None,
+                    }
+                }
+
+                if let Some(recorder) = &mut recorder {
+                    recorder.record(input_frame, machine.pressed_key)?;
+                }
+                input_frame += 1;
 
-        canvas.set_draw_color(Color::BLACK);
-        for x in 0..WIDTH {
-            for y in 0..HEIGHT {
-                if (machine.canvas[y] >> x) & 1 == 1 {
-                    let rect = Rect::new(
-                        (PIXEL_SIZE * x) as i32,
-                        (PIXEL_SIZE * y) as i32,
-                        PIXEL_SIZE as u32,
-                        PIXEL_SIZE as u32,
-                    );
-                    rects.push(rect);
+                let mut watch_triggered = false;
+                let mut cycles = if turbo {
+                    instructions_per_frame * TURBO_FACTOR
+                } else {
+                    instructions_per_frame
+                };
+                if let Some(limit) = instruction_limit {
+                    let remaining = limit.saturating_sub(machine.instruction_count());
+                    cycles = cycles.min(remaining.try_into().unwrap_or(u32::MAX));
                 }
+                machine.run_frame(cycles, |machine, pc| {
+                    watch_triggered = watchpoints.check(machine, pc);
+                    watch_triggered
+                })?;
+                if let Some(key) = repeated_key {
+                    machine.pressed_key = Some(key);
+                }
+                if watch_triggered {
+                    frame_step = true;
+                    display.show_toast("Watch triggered", TOAST_FRAMES);
+                }
+                step_requested = false;
             }
+
+            if machine.is_halted() {
+                println!("Halted at {:#05X}", machine.pc());
+                break 'main;
+            }
+            if instruction_limit.is_some_and(|limit| machine.instruction_count() >= limit) {
+                print_speed(machine, start);
+                break 'main;
+            }
+
+            if machine.xo_chip {
+                audio.set_pattern(machine.audio_pattern(), machine.pitch());
+            }
+            if beep.update(running && !rewinding && machine.is_beeping()) {
+                audio.set_playing(beep.playing());
+            }
+        }
+
+        if let Some(collisions) = machine.collisions() {
+            display.highlight(collisions);
         }
+        display.present(machine.canvas())?;
 
-        canvas.fill_rects(&rects).map_err(|err| anyhow!(err))?;
-        canvas.present();
+        if flags.show_stats {
+            stats_frames += 1;
+            let elapsed = stats_since.elapsed();
+            if elapsed >= Duration::from_secs(1) {
+                let instructions = machine.instruction_count();
+                let seconds = elapsed.as_secs_f64();
+                let mut stats = format!(
+                    "{:.0} FPS {:.0} IPS",
+                    stats_frames as f64 / seconds,
+                    instructions.saturating_sub(stats_instructions) as f64 / seconds
+                );
+                if let Some((last_frame, _)) = machine.draw_timing() {
+                    stats.push_str(&format!(" DRW {:.0}%", last_frame.draw_share() * 100.0));
+                }
+                display.show_stats(&stats);
+                stats_since = Instant::now();
+                stats_frames = 0;
+                stats_instructions = instructions;
+            }
+        }
 
-        // Read events for the remaining frame time
-        while now.elapsed() < FRAME_TIME {
-            if let Some(event) = event_pump.wait_event_timeout(now.elapsed().as_millis() as u32) {
+        // Read events until the next frame is due
+        let next_frame = last_update + (FRAME_TIME - lag);
+        while let Some(remaining) = next_frame.checked_duration_since(Instant::now()) {
+            if remaining.is_zero() {
+                break;
+            }
+            if let Some(event) = event_pump.wait_event_timeout(remaining.as_millis() as u32) {
                 match event {
                     Event::Quit { .. }
                     | Event::KeyDown {
@@ -127,13 +509,172 @@ fn main() -> Result<()> {
                         ..
                     } => break 'main,
                     Event::KeyDown {
-                        scancode, repeat, ..
+                        keycode: Some(Keycode::Return),
+                        ..
+                    } if frame_step => step_requested = true,
+                    Event::KeyDown {
+                        keycode: Some(Keycode::Space),
+                        repeat: false,
+                        ..
+                    } => {
+                        paused = !paused;
+                        if paused {
+                            display.show_toast("Paused", u32::MAX);
+                        } else {
+                            display.show_toast("Resumed", TOAST_FRAMES);
+                        }
+                    }
+                    Event::KeyDown {
+                        keycode: Some(Keycode::N),
+                        ..
+                    } if paused => machine.step()?,
+                    Event::KeyDown {
+                        keycode: Some(Keycode::F1),
+                        ..
+                    } => {
+                        machine.reset();
+                        display.show_toast("Reset", TOAST_FRAMES);
+                    }
+                    Event::KeyDown {
+                        keycode: Some(Keycode::F5),
+                        ..
+                    } => {
+                        let config = flags
+                            .state_config
+                            .then(|| RunConfig::capture(machine, instructions_per_frame));
+                        match save_state::save(&state_path, machine, config.as_ref()) {
+                            Ok(()) => display.show_toast("State saved", TOAST_FRAMES),
+                            Err(err) => eprintln!("{:#}", err),
+                        }
+                    }
+                    Event::KeyDown {
+                        keycode: Some(Keycode::F9),
+                        ..
+                    } => match save_state::load(&state_path, machine) {
+                        Ok(config) => {
+                            if let Some(config) = config {
+                                if let Err(err) = config.apply(machine) {
+                                    eprintln!("{:#}", err);
+                                }
+                                instructions_per_frame = config.instructions_per_frame;
+                            }
+                            display.show_toast("State loaded", TOAST_FRAMES);
+                        }
+                        Err(err) => eprintln!("{:#}", err),
+                    },
+                    Event::KeyDown {
+                        keycode: Some(Keycode::F12),
+                        ..
+                    } => {
+                        if let Some(path) = &flags.memdump {
+                            match write_memdump(path, machine) {
+                                Ok(()) => display.show_toast("Memory dumped", TOAST_FRAMES),
+                                Err(err) => eprintln!("{:#}", err),
+                            }
+                        }
+                    }
+                    Event::KeyDown {
+                        keycode: Some(Keycode::F2),
+                        ..
+                    } => {
+                        scanlines = !scanlines;
+                        display.set_scanlines(scanlines);
+                        display.show_toast(
+                            if scanlines {
+                                "Scanlines on"
+                            } else {
+                                "Scanlines off"
+                            },
+                            TOAST_FRAMES,
+                        );
+                    }
+                    Event::KeyDown {
+                        keycode: Some(Keycode::F7),
+                        ..
+                    } => {
+                        font_index = (font_index + 1) % characters::FONTS.len();
+                        let (name, font) = characters::FONTS[font_index];
+                        machine.switch_font(font);
+                        display.show_toast(&format!("Font {}", name), TOAST_FRAMES);
+                    }
+                    Event::KeyDown {
+                        keycode: Some(Keycode::F11),
+                        ..
+                    } => match clipboard::copy_canvas(machine, scale, foreground, background) {
+                        Ok(()) => display.show_toast("Frame copied", TOAST_FRAMES),
+                        Err(err) => {
+                            eprintln!("Failed to copy frame to clipboard: {:#}", err)
+                        }
+                    },
+                    Event::KeyDown {
+                        keycode: Some(keycode @ (Keycode::LeftBracket | Keycode::RightBracket)),
+                        ..
+                    } => {
+                        let frequency = instructions_per_frame * REFRESH_RATE;
+                        let frequency = match keycode {
+                            Keycode::LeftBracket => frequency.saturating_sub(FREQUENCY_STEP),
+                            _ => frequency + FREQUENCY_STEP,
+                        };
+                        instructions_per_frame =
+                            frequency.clamp(REFRESH_RATE, MAX_FREQUENCY) / REFRESH_RATE;
+                        display.show_toast(
+                            &format!("{} Hz", instructions_per_frame * REFRESH_RATE),
+                            TOAST_FRAMES,
+                        );
+                    }
+                    Event::KeyDown {
+                        keycode: Some(Keycode::Tab),
+                        repeat: false,
+                        ..
+                    } => {
+                        turbo = true;
+                        display.show_toast("Fast forward", TOAST_FRAMES);
+                    }
+                    Event::KeyUp {
+                        keycode: Some(Keycode::Tab),
+                        ..
+                    } => turbo = false,
+                    Event::KeyDown {
+                        keycode: Some(Keycode::Backspace),
+                        ..
+                    } => rewinding = true,
+                    Event::KeyUp {
+                        keycode: Some(Keycode::Backspace),
+                        ..
+                    } => rewinding = false,
+                    Event::KeyDown {
+                        scancode,
+                        repeat: false,
+                        ..
                     } => {
-                        if !repeat {
-                            // Set pressed key
-                            machine.pressed_key = scancode_to_chip8_code(scancode);
+                        // Set pressed key
+                        machine.pressed_key = key_bindings
+                            .iter()
+                            .find(|&&(bound, _)| Some(bound) == scancode)
+                            .map(|&(_, key)| key)
+                            .or_else(|| scancode_to_chip8_code(scancode));
+                    }
+                    Event::ControllerDeviceAdded { which, .. } => {
+                        match controller_subsystem.open(which) {
+                            Ok(controller) => controllers.push(controller),
+                            Err(err) => eprintln!("Failed to open controller: {}", err),
+                        }
+                    }
+                    Event::ControllerDeviceRemoved { which, .. } => {
+                        controllers.retain(|controller| controller.instance_id() != which);
+                    }
+                    Event::ControllerButtonDown { button, .. } => {
+                        if let Some(key) = pad_mapping.key(button) {
+                            machine.pressed_key = Some(key);
                         }
                     }
+                    Event::ControllerButtonUp { button, .. }
+                        if pad_mapping
+                            .key(button)
+                            .is_some_and(|key| machine.pressed_key == Some(key)) =>
+                    {
+                        machine.pressed_key = None;
+                    }
                     Event::KeyUp { .. } => {
                         // Reset pressed key
                         machine.pressed_key = None;
@@ -144,9 +685,162 @@ fn main() -> Result<()> {
         }
     }
 
+    if let Some(recorder) = &mut recorder {
+        recorder.flush()?;
+    }
+    report_exit(flags, machine)
+}
+
+/// Prints the time since `start`, the instructions executed and the
+/// resulting millions of instructions per second
+fn print_speed(machine: &VirtualMachine, start: Instant) {
+    let elapsed = start.elapsed();
+    let instructions = machine.instruction_count();
+    println!("Time: {:.3}s", elapsed.as_secs_f64());
+    println!("Instructions: {}", instructions);
+    println!(
+        "MIPS: {:.2}",
+        instructions as f64 / 1_000_000.0 / elapsed.as_secs_f64()
+    );
+}
+
+/// Parses a hexadecimal number with an optional `0x` prefix, `what` names
+/// the number in the error
+fn parse_hex(text: &str, what: &str) -> Result<u16> {
+    u16::from_str_radix(text.trim_start_matches("0x"), 16)
+        .with_context(|| format!("Invalid {}: {}", what, text))
+}
+
+/// A path of `-` reads the ROM from stdin
+fn read_rom(path: &Path) -> Result<Vec<u8>> {
+    if path != Path::new("-") {
+        return std::fs::read(path).with_context(|| format!("Failed to read ROM: {:?}", path));
+    }
+
+    let mut rom = Vec::new();
+    std::io::stdin()
+        .read_to_end(&mut rom)
+        .context("Failed to read ROM from stdin")?;
+    Ok(rom)
+}
+
+/// Output requested for when the emulator stops
+fn report_exit(flags: &flags::Main, machine: &mut VirtualMachine) -> Result<()> {
+    machine.flush_trace()?;
+
+    if let Some(path) = &flags.dump_state {
+        dump_state(path, machine)?;
+    }
+    if let Some(path) = &flags.memdump {
+        write_memdump(path, machine)?;
+    }
+
+    if flags.profile_waits {
+        match machine.average_wait() {
+            Some((average, count)) => println!(
+                "Delay timer waits: {} with {:.1} instructions on average",
+                count, average
+            ),
+            None => println!("Delay timer waits: none"),
+        }
+    }
+
+    if let Some((_, total)) = machine.draw_timing() {
+        println!(
+            "Draw time: {:.1}ms of {:.1}ms ({:.1}%)",
+            total.draw.as_secs_f64() * 1000.0,
+            (total.draw + total.other).as_secs_f64() * 1000.0,
+            total.draw_share() * 100.0
+        );
+    }
+
+    if flags.profile {
+        let histogram = machine.opcode_histogram();
+        let total: u64 = histogram.iter().map(|(_, count)| count).sum();
+        for (class, count) in histogram {
+            eprintln!(
+                "{}  {:>12}  {:5.1}%",
+                class,
+                count,
+                count as f64 * 100.0 / total as f64
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Lists every aligned word of the ROM that would execute as an invalid
+/// opcode. Data such as sprites shows up too.
+fn verify(machine: &VirtualMachine) -> Result<()> {
+    let mut invalid = 0;
+    for (address, op) in machine.decode_all() {
+        if let DecodedOp::Unknown(opcode) = op {
+            println!("{:03X}: {:04X}", address, opcode);
+            invalid += 1;
+        }
+    }
+
+    if invalid > 0 {
+        bail!("Found {} undecodable opcodes", invalid);
+    }
+    println!("All opcodes are decodable");
+    Ok(())
+}
+
+/// Writes the raw 4K memory image, on exit or when F12 is pressed
+fn write_memdump(path: &Path, machine: &VirtualMachine) -> Result<()> {
+    std::fs::write(path, machine.memory_image())
+        .with_context(|| format!("Failed to write memory dump: {:?}", path))
+}
+
+/// Loads each `ADDR=FILE` segment, rejecting ones that overlap the ROM or each other
+fn load_segments(machine: &mut VirtualMachine, segments: &[String]) -> Result<()> {
+    let rom = machine.load_address() as usize..machine.rom_end() as usize;
+    let mut loaded = vec![rom];
+
+    for segment in segments {
+        let Some((address, path)) = segment.split_once('=') else {
+            bail!("Invalid segment: {} (expected ADDR=FILE)", segment);
+        };
+        let address = parse_hex(address, "segment address")?;
+        let data =
+            std::fs::read(path).with_context(|| format!("Failed to read segment: {:?}", path))?;
+
+        let range = address as usize..address as usize + data.len();
+        if let Some(other) = loaded
+            .iter()
+            .find(|other| range.start < other.end && other.start < range.end)
+        {
+            bail!(
+                "Segment {:#05X}..{:#05X} overlaps {:#05X}..{:#05X}",
+                range.start,
+                range.end,
+                other.start,
+                other.end
+            );
+        }
+
+        machine.load_segment(address, &data)?;
+        loaded.push(range);
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "serde")]
+fn dump_state(path: &Path, machine: &VirtualMachine) -> Result<()> {
+    let file = std::fs::File::create(path)
+        .with_context(|| format!("Failed to create state dump: {:?}", path))?;
+    serde_json::to_writer_pretty(std::io::BufWriter::new(file), machine)?;
     Ok(())
 }
 
+#[cfg(not(feature = "serde"))]
+fn dump_state(_path: &Path, _machine: &VirtualMachine) -> Result<()> {
+    bail!("--dump-state requires the serde feature")
+}
+
 fn scancode_to_chip8_code(scancode: Option<Scancode>) -> Option<u8> {
     scancode.and_then(|code| match code {
         Scancode::Num1 => Some(0x1),
@@ -168,3 +862,42 @@ fn scancode_to_chip8_code(scancode: Option<Scancode>) -> Option<u8> {
         _ => None,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes `data` to a file unique to this test process
+    fn temp_file(name: &str, data: &[u8]) -> String {
+        let path = std::env::temp_dir().join(format!("chip-8-{}-{}", std::process::id(), name));
+        std::fs::write(&path, data).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn hex_numbers_parse_with_or_without_a_prefix() {
+        assert_eq!(parse_hex("0x2A0", "address").unwrap(), 0x2A0);
+        assert_eq!(parse_hex("fff", "address").unwrap(), 0xFFF);
+        let err = parse_hex("0xZZ", "load address").unwrap_err();
+        assert_eq!(err.to_string(), "Invalid load address: 0xZZ");
+    }
+
+    #[test]
+    fn segments_load_at_their_addresses() {
+        let code = temp_file("code.bin", &[0x12, 0x34]);
+        let data = temp_file("data.bin", &[0xAB, 0xCD, 0xEF]);
+        let mut machine = VirtualMachine::from_bytes(&[0x00, 0xE0]).unwrap();
+
+        load_segments(
+            &mut machine,
+            &[format!("300={}", code), format!("0xA00={}", data)],
+        )
+        .unwrap();
+        assert_eq!(machine.memory_slice(0x300, 2).unwrap(), [0x12, 0x34]);
+        assert_eq!(machine.memory_slice(0xA00, 3).unwrap(), [0xAB, 0xCD, 0xEF]);
+
+        let overlapping = [format!("600={}", code), format!("601={}", code)];
+        assert!(load_segments(&mut machine, &overlapping).is_err());
+        assert!(load_segments(&mut machine, &[format!("200={}", data)]).is_err());
+    }
+}
@@ -0,0 +1,44 @@
+use std::collections::VecDeque;
+
+use anyhow::Result;
+
+use crate::save_state;
+use crate::virtual_machine::VirtualMachine;
+
+/// One snapshot is a full save state, a little over 4 KiB, so the
+/// maximum of 3600 frames (one minute) costs about 16 MiB
+pub const MAX_REWIND_FRAMES: u32 = 3600;
+
+/// Ring buffer of per-frame snapshots, oldest first
+pub struct Rewind {
+    states: VecDeque<Vec<u8>>,
+    capacity: usize,
+}
+
+impl Rewind {
+    pub fn new(frames: u32) -> Self {
+        let capacity = frames.min(MAX_REWIND_FRAMES) as usize;
+        Self {
+            states: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, machine: &VirtualMachine) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.states.len() == self.capacity {
+            self.states.pop_front();
+        }
+        self.states.push_back(save_state::encode(machine, None));
+    }
+
+    /// Steps `machine` one frame back, does nothing once the buffer is empty
+    pub fn pop(&mut self, machine: &mut VirtualMachine) -> Result<()> {
+        if let Some(state) = self.states.pop_back() {
+            save_state::decode(machine, &state)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,259 @@
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+
+use crate::virtual_machine::{IndexIncrement, Quirks, Reader, VirtualMachine, MAX_STACK_DEPTH};
+
+const MAGIC: &[u8; 4] = b"C8ST";
+const STATE_VERSION: u8 = 4;
+const CONFIG_VERSION: u8 = 2;
+/// Same upper bound as changing the speed with `[` and `]`
+const MAX_INSTRUCTIONS_PER_FRAME: u32 = crate::MAX_FREQUENCY / crate::REFRESH_RATE;
+
+/// Execution environment that can be stored next to the machine state,
+/// so loading a state reproduces the run exactly
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RunConfig {
+    pub seed: u64,
+    pub instructions_per_frame: u32,
+    pub quirks: Quirks,
+    pub stack_depth: usize,
+    pub frame_rng_seed: Option<u64>,
+}
+
+impl RunConfig {
+    /// The environment `machine` currently runs in
+    pub fn capture(machine: &VirtualMachine, instructions_per_frame: u32) -> Self {
+        Self {
+            seed: machine.rng_seed(),
+            instructions_per_frame,
+            quirks: machine.quirks(),
+            stack_depth: machine.stack_depth(),
+            frame_rng_seed: machine.frame_rng_seed,
+        }
+    }
+
+    /// Everything but the instructions per frame, which belong to the frontend
+    pub fn apply(&self, machine: &mut VirtualMachine) -> Result<()> {
+        machine.set_stack_depth(self.stack_depth)?;
+        machine.set_quirks(self.quirks);
+        machine.seed_rng(self.seed);
+        machine.frame_rng_seed = self.frame_rng_seed;
+        Ok(())
+    }
+
+    fn write(&self, out: &mut Vec<u8>) {
+        out.push(CONFIG_VERSION);
+        out.extend_from_slice(&self.seed.to_le_bytes());
+        out.extend_from_slice(&self.instructions_per_frame.to_le_bytes());
+
+        let quirks = &self.quirks;
+        let flags = [
+            quirks.rom_readonly,
+            quirks.strict,
+            quirks.xo_chip,
+            quirks.stack_wrap,
+            quirks.jump_with_vx,
+            quirks.logic_resets_vf,
+            quirks.wrap_sprites,
+            quirks.i_overflow_sets_vf,
+            quirks.halt_on_idle,
+            quirks.halt_on_top_return,
+        ];
+        let bits = flags
+            .iter()
+            .enumerate()
+            .fold(0u16, |bits, (bit, &set)| bits | ((set as u16) << bit));
+        out.extend_from_slice(&bits.to_le_bytes());
+        out.push(match quirks.load_store_increments_i {
+            IndexIncrement::None => 0,
+            IndexIncrement::ByX => 1,
+            IndexIncrement::ByXPlusOne => 2,
+        });
+
+        out.push(self.stack_depth as u8);
+        match self.frame_rng_seed {
+            Some(seed) => {
+                out.push(1);
+                out.extend_from_slice(&seed.to_le_bytes());
+            }
+            None => out.push(0),
+        }
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self> {
+        let version = reader.u8()?;
+        if version != CONFIG_VERSION {
+            bail!("Unsupported config version: {}", version);
+        }
+
+        let seed = reader.u64()?;
+        let instructions_per_frame = reader.u32()?;
+        if !(1..=MAX_INSTRUCTIONS_PER_FRAME).contains(&instructions_per_frame) {
+            bail!(
+                "Invalid instructions per frame in config: {}",
+                instructions_per_frame
+            );
+        }
+
+        let bits = reader.u16()?;
+        let bit = |bit: u16| bits & (1 << bit) != 0;
+        let load_store_increments_i = match reader.u8()? {
+            0 => IndexIncrement::None,
+            1 => IndexIncrement::ByX,
+            2 => IndexIncrement::ByXPlusOne,
+            other => bail!("Invalid load/store increment in config: {}", other),
+        };
+        let quirks = Quirks {
+            rom_readonly: bit(0),
+            strict: bit(1),
+            xo_chip: bit(2),
+            stack_wrap: bit(3),
+            jump_with_vx: bit(4),
+            logic_resets_vf: bit(5),
+            load_store_increments_i,
+            wrap_sprites: bit(6),
+            i_overflow_sets_vf: bit(7),
+            halt_on_idle: bit(8),
+            halt_on_top_return: bit(9),
+        };
+
+        let stack_depth = reader.u8()? as usize;
+        if stack_depth == 0 || stack_depth > MAX_STACK_DEPTH {
+            bail!("Invalid stack depth in config: {}", stack_depth);
+        }
+        let frame_rng_seed = match reader.u8()? {
+            0 => None,
+            _ => Some(reader.u64()?),
+        };
+
+        Ok(Self {
+            seed,
+            instructions_per_frame,
+            quirks,
+            stack_depth,
+            frame_rng_seed,
+        })
+    }
+}
+
+pub fn encode(machine: &VirtualMachine, config: Option<&RunConfig>) -> Vec<u8> {
+    let mut out = Vec::with_capacity(0x1200);
+    out.extend_from_slice(MAGIC);
+    out.push(STATE_VERSION);
+
+    match config {
+        Some(config) => {
+            out.push(1);
+            config.write(&mut out);
+        }
+        None => out.push(0),
+    }
+    machine.write_state(&mut out);
+
+    out
+}
+
+/// Restores `machine` from `bytes` and returns the stored config, if any.
+/// The config comes first, so an invalid one leaves the machine unchanged.
+pub fn decode(machine: &mut VirtualMachine, bytes: &[u8]) -> Result<Option<RunConfig>> {
+    let mut reader = Reader::new(bytes);
+
+    if reader.bytes(MAGIC.len())? != MAGIC {
+        bail!("Not a save state");
+    }
+    let version = reader.u8()?;
+    if version != STATE_VERSION {
+        bail!("Unsupported save state version: {}", version);
+    }
+
+    let config = match reader.u8()? {
+        0 => None,
+        _ => Some(RunConfig::read(&mut reader)?),
+    };
+    machine.read_state(&mut reader)?;
+
+    Ok(config)
+}
+
+pub fn save(path: &Path, machine: &VirtualMachine, config: Option<&RunConfig>) -> Result<()> {
+    std::fs::write(path, encode(machine, config))
+        .with_context(|| format!("Failed to write save state: {:?}", path))
+}
+
+pub fn load(path: &Path, machine: &mut VirtualMachine) -> Result<Option<RunConfig>> {
+    let bytes =
+        std::fs::read(path).with_context(|| format!("Failed to read save state: {:?}", path))?;
+    decode(machine, &bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn state_and_config_round_trip() {
+        // Call 0x206, which sets V0 and the timers
+        let mut machine = VirtualMachine::from_bytes(&[
+            0x22, 0x06, 0x00, 0x00, 0x00, 0x00, 0x60, 0xAB, 0xF0, 0x15, 0xF0, 0x18,
+        ])
+        .unwrap();
+        machine.set_quirks(Quirks {
+            xo_chip: true,
+            jump_with_vx: true,
+            load_store_increments_i: IndexIncrement::ByXPlusOne,
+            halt_on_top_return: true,
+            ..Quirks::default()
+        });
+        machine.set_stack_depth(12).unwrap();
+        machine.frame_rng_seed = Some(42);
+        machine.seed_rng(7);
+        machine.run_frame(4, |_, _| false).unwrap();
+        let config = RunConfig::capture(&machine, 700);
+        let bytes = encode(&machine, Some(&config));
+
+        let mut restored = VirtualMachine::from_bytes(&[0x00, 0xE0]).unwrap();
+        let restored_config = decode(&mut restored, &bytes).unwrap().unwrap();
+        assert_eq!(restored_config, config);
+        restored_config.apply(&mut restored).unwrap();
+
+        assert_eq!(restored.quirks(), machine.quirks());
+        assert_eq!(restored.stack_depth(), 12);
+        assert_eq!(restored.frame_rng_seed, Some(42));
+        assert_eq!(restored.rng_seed(), machine.rng_seed());
+        assert_eq!(encode(&restored, Some(&config)), bytes);
+    }
+
+    #[test]
+    fn stack_entries_outside_memory_are_rejected() {
+        let mut machine = VirtualMachine::from_bytes(&[0x22, 0x02, 0x00, 0xE0]).unwrap();
+        machine.step().unwrap();
+        let mut bytes = encode(&machine, None);
+
+        // The only stack entry follows the header, the missing config,
+        // memory, registers, I, pc, the ROM end, both timers and the stack length
+        let entry = MAGIC.len() + 1 + 1 + 0x1000 + 16 + 2 + 2 + 2 + 1 + 1 + 1;
+        assert_eq!(bytes[entry..entry + 2], 0x202u16.to_le_bytes());
+        bytes[entry..entry + 2].copy_from_slice(&0x1202u16.to_le_bytes());
+
+        let mut restored = VirtualMachine::from_bytes(&[0x00, 0xE0]).unwrap();
+        assert!(decode(&mut restored, &bytes).is_err());
+    }
+
+    #[test]
+    fn invalid_configs_are_rejected_before_touching_the_machine() {
+        let machine = VirtualMachine::from_bytes(&[0x60, 0xAB]).unwrap();
+        for (instructions_per_frame, stack_depth) in [(0, 16), (u32::MAX, 16), (10, 0)] {
+            let config = RunConfig {
+                instructions_per_frame,
+                stack_depth,
+                ..RunConfig::capture(&machine, 10)
+            };
+            let bytes = encode(&machine, Some(&config));
+
+            let mut restored = VirtualMachine::from_bytes(&[0x00, 0xE0]).unwrap();
+            assert!(decode(&mut restored, &bytes).is_err());
+            assert_eq!(restored.memory_slice(0x200, 2).unwrap(), [0x00, 0xE0]);
+        }
+    }
+}
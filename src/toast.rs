@@ -0,0 +1,106 @@
+use anyhow::{anyhow, Result};
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::WindowCanvas;
+
+use crate::characters;
+
+/// Short message overlaid on the screen for a fixed number of frames
+#[derive(Default)]
+pub struct Toast {
+    text: String,
+    remaining_frames: u32,
+}
+
+impl Toast {
+    /// Replaces any message currently shown
+    pub fn show_toast(&mut self, text: &str, duration_frames: u32) {
+        self.text.clear();
+        self.text.push_str(text);
+        self.remaining_frames = duration_frames;
+    }
+
+    pub fn text(&self) -> Option<&str> {
+        (self.remaining_frames > 0).then_some(self.text.as_str())
+    }
+
+    /// Called once per rendered frame
+    pub fn tick(&mut self) {
+        self.remaining_frames = self.remaining_frames.saturating_sub(1);
+    }
+
+    /// Draws the message on a bar in the bottom-left corner, `pixel` host
+    /// pixels per font pixel
+    pub fn render(&self, canvas: &mut WindowCanvas, pixel: u32) -> Result<()> {
+        let Some(text) = self.text() else {
+            return Ok(());
+        };
+
+        let (_, window_height) = canvas.output_size().map_err(|err| anyhow!(err))?;
+        render_bar(canvas, text, pixel, window_height as i32 - 7 * pixel as i32)
+    }
+}
+
+/// Draws `text` on a bar along the left edge starting at row `y`, `pixel`
+/// host pixels per font pixel. Characters without a glyph are skipped.
+pub fn render_bar(canvas: &mut WindowCanvas, text: &str, pixel: u32, y: i32) -> Result<()> {
+    let glyphs: Vec<_> = text.chars().filter_map(characters::glyph).collect();
+    let advance = 5 * pixel as i32;
+    let bar = Rect::new(
+        0,
+        y,
+        (glyphs.len() as i32 * advance + pixel as i32) as u32,
+        7 * pixel,
+    );
+
+    canvas.set_draw_color(Color::RGB(0x40, 0x40, 0x40));
+    canvas.fill_rect(bar).map_err(|err| anyhow!(err))?;
+
+    let mut rects = Vec::new();
+    for (index, glyph) in glyphs.iter().enumerate() {
+        for (row, bits) in glyph.iter().enumerate() {
+            for column in 0..4 {
+                if (bits << column) & 0x80 != 0 {
+                    rects.push(Rect::new(
+                        pixel as i32 + index as i32 * advance + column * pixel as i32,
+                        bar.y() + pixel as i32 * (row as i32 + 1),
+                        pixel,
+                        pixel,
+                    ));
+                }
+            }
+        }
+    }
+
+    canvas.set_draw_color(Color::WHITE);
+    canvas.fill_rects(&rects).map_err(|err| anyhow!(err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toast_is_visible_for_exactly_its_duration() {
+        let mut toast = Toast::default();
+        assert_eq!(toast.text(), None);
+
+        toast.show_toast("Paused", 3);
+        for _ in 0..3 {
+            assert_eq!(toast.text(), Some("Paused"));
+            toast.tick();
+        }
+        assert_eq!(toast.text(), None);
+    }
+
+    #[test]
+    fn new_toast_replaces_the_current_one() {
+        let mut toast = Toast::default();
+        toast.show_toast("Paused", 3);
+        toast.tick();
+        toast.show_toast("Muted", 1);
+        assert_eq!(toast.text(), Some("Muted"));
+        toast.tick();
+        assert_eq!(toast.text(), None);
+    }
+}
@@ -0,0 +1,196 @@
+use std::io::{Stdout, Write};
+use std::time::Instant;
+
+use anyhow::Result;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::style::Print;
+use crossterm::{cursor, queue, terminal};
+
+use crate::canvas::Canvas;
+use crate::display::Display;
+use crate::virtual_machine::VirtualMachine;
+use crate::{FRAME_TIME, HEIGHT, WIDTH};
+
+/// Terminals only report key presses, so a key counts as held for this many
+/// frames after its last press or repeat
+const KEY_HOLD_FRAMES: u32 = 6;
+
+/// Renders two pixel rows per character cell with half blocks
+pub struct TuiDisplay {
+    stdout: Stdout,
+    toast: String,
+    toast_frames: u32,
+    stats: String,
+}
+
+impl TuiDisplay {
+    /// Switches the terminal to the alternate screen until dropped
+    pub fn new() -> Result<Self> {
+        let mut stdout = std::io::stdout();
+        terminal::enable_raw_mode()?;
+        queue!(stdout, terminal::EnterAlternateScreen, cursor::Hide)?;
+        stdout.flush()?;
+
+        Ok(Self {
+            stdout,
+            toast: String::new(),
+            toast_frames: 0,
+            stats: String::new(),
+        })
+    }
+}
+
+impl Drop for TuiDisplay {
+    fn drop(&mut self) {
+        let _ = queue!(self.stdout, cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = self.stdout.flush();
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+impl Display for TuiDisplay {
+    fn present(&mut self, canvas: &[Canvas; 2]) -> Result<()> {
+        let lit = |x: usize, y: usize| canvas[0].get_pixel(x, y) || canvas[1].get_pixel(x, y);
+
+        let mut frame = String::with_capacity((WIDTH + 2) * (HEIGHT / 2 + 1) * 3);
+        for y in (0..HEIGHT).step_by(2) {
+            for x in 0..WIDTH {
+                frame.push(match (lit(x, y), lit(x, y + 1)) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                });
+            }
+            frame.push_str("\r\n");
+        }
+
+        if !self.stats.is_empty() {
+            frame.push_str(&self.stats);
+            frame.push_str("\r\n");
+        }
+        if self.toast_frames > 0 {
+            self.toast_frames -= 1;
+            frame.push_str(&self.toast);
+        }
+
+        queue!(
+            self.stdout,
+            cursor::MoveTo(0, 0),
+            terminal::Clear(terminal::ClearType::FromCursorDown),
+            Print(frame)
+        )?;
+        self.stdout.flush()?;
+
+        Ok(())
+    }
+
+    /// The terminal has a fixed cell size
+    fn set_scale(&mut self, _scale: u32) -> Result<()> {
+        Ok(())
+    }
+
+    fn show_toast(&mut self, text: &str, duration_frames: u32) {
+        self.toast.clear();
+        self.toast.push_str(text);
+        self.toast_frames = duration_frames;
+    }
+
+    /// Half blocks leave no room for a third color
+    fn highlight(&mut self, _pixels: &Canvas) {}
+
+    fn show_stats(&mut self, text: &str) {
+        self.stats.clear();
+        self.stats.push_str(text);
+    }
+
+    fn set_scanlines(&mut self, _scanlines: bool) {}
+}
+
+/// Runs the machine in the terminal until Escape or Ctrl-C is pressed, or
+/// until it executed `instruction_limit` instructions or `deadline` passed
+pub fn run(
+    machine: &mut VirtualMachine,
+    instructions_per_frame: u32,
+    instruction_limit: Option<u64>,
+    deadline: Option<Instant>,
+) -> Result<()> {
+    let mut display = TuiDisplay::new()?;
+    let mut held_frames: u32 = 0;
+
+    loop {
+        let now = Instant::now();
+
+        let mut cycles = instructions_per_frame;
+        if let Some(limit) = instruction_limit {
+            let remaining = limit.saturating_sub(machine.instruction_count());
+            cycles = cycles.min(remaining.try_into().unwrap_or(u32::MAX));
+        }
+        machine.run_frame(cycles, |_, _| false)?;
+        if machine.is_halted()
+            || instruction_limit.is_some_and(|limit| machine.instruction_count() >= limit)
+            || deadline.is_some_and(|deadline| now >= deadline)
+        {
+            break;
+        }
+
+        held_frames = held_frames.saturating_sub(1);
+        if held_frames == 0 {
+            machine.pressed_key = None;
+        }
+
+        display.present(machine.canvas())?;
+
+        // Read events for the remaining frame time
+        while let Some(remaining) = FRAME_TIME.checked_sub(now.elapsed()) {
+            if !event::poll(remaining)? {
+                break;
+            }
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind == KeyEventKind::Release {
+                continue;
+            }
+
+            match key.code {
+                KeyCode::Esc => return Ok(()),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    return Ok(())
+                }
+                KeyCode::Char(c) => {
+                    if let Some(code) = char_to_chip8_code(c) {
+                        machine.pressed_key = Some(code);
+                        held_frames = KEY_HOLD_FRAMES;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Same layout as the SDL keyboard mapping
+fn char_to_chip8_code(c: char) -> Option<u8> {
+    match c.to_ascii_lowercase() {
+        '1' => Some(0x1),
+        '2' => Some(0x2),
+        '3' => Some(0x3),
+        '4' => Some(0xC),
+        'q' => Some(0x4),
+        'w' => Some(0x5),
+        'e' => Some(0x6),
+        'r' => Some(0xD),
+        'a' => Some(0x7),
+        's' => Some(0x8),
+        'd' => Some(0x9),
+        'f' => Some(0xE),
+        'z' => Some(0xA),
+        'x' => Some(0x0),
+        'c' => Some(0xB),
+        'v' => Some(0xF),
+        _ => None,
+    }
+}
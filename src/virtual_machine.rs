@@ -1,68 +1,672 @@
-use std::path::Path;
+use std::fmt::Write as _;
+use std::io::{BufWriter, Write};
+use std::ops::Range;
+use std::time::{Duration, Instant};
 
 use arrayvec::ArrayVec;
 
-use crate::{characters, HEIGHT};
+use crate::canvas::{self, Canvas};
+use crate::decode::DecodedOp;
+use crate::{characters, HEIGHT, WIDTH};
 use anyhow::{bail, Context, Result};
 
+/// Where programs are loaded and start unless `--load-address` says otherwise
+pub const DEFAULT_LOAD_ADDRESS: u16 = 0x200;
+/// The font occupies 0x050..0x0A0 unless relocated, programs and
+/// segments must not overlap it
+const FONT_BASE: u16 = 0x50;
+const FONT_SIZE: usize = 80;
+
+/// Upper bound for `--stack-depth`, real hardware had 12 or 16 levels
+pub const MAX_STACK_DEPTH: usize = 100;
+
+/// Writes here are echoed to stderr as ASCII when `debug_port` is enabled
+const DEBUG_PORT: Range<u16> = 0xF00..0xF10;
+
+/// Fails unless a font at `base` fits in memory without overlapping `program`
+fn check_font_placement(base: u16, program: Range<usize>) -> Result<()> {
+    let font = base as usize..base as usize + FONT_SIZE;
+    if font.end > 0x1000 {
+        bail!("Font at {:#05X} does not fit in memory", base);
+    }
+    if font.start < program.end && program.start < font.end {
+        bail!(
+            "Font at {:#05X}..{:#05X} overlaps the ROM at {:#05X}..{:#05X}",
+            font.start,
+            font.end,
+            program.start,
+            program.end
+        );
+    }
+    Ok(())
+}
+
+/// Describes what is likely wrong with a ROM of `len` bytes, if anything.
+/// Loading only rejects it in strict mode, empty ROMs are always rejected.
+pub fn rom_problem(len: usize) -> Option<String> {
+    (!len.is_multiple_of(2)).then(|| format!("ROM has an odd length of {} bytes", len))
+}
+
 #[derive(Debug)]
 enum Relation {
     Equal,
     NotEqual,
 }
 
+/// How FX55 and FX65 leave I, each one is found in some historical interpreter
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum IndexIncrement {
+    /// I is unchanged, as on SUPER-CHIP
+    #[default]
+    None,
+    /// I advances by X, as on CHIP-48
+    ByX,
+    /// I advances by X + 1, as on the COSMAC VIP
+    ByXPlusOne,
+}
+
+impl IndexIncrement {
+    /// Accepts `none`, `x` or `x+1`
+    pub fn parse(text: &str) -> Result<Self> {
+        match text {
+            "none" => Ok(Self::None),
+            "x" => Ok(Self::ByX),
+            "x+1" => Ok(Self::ByXPlusOne),
+            _ => bail!(
+                "Invalid load/store increment: {} (expected none, x or x+1)",
+                text
+            ),
+        }
+    }
+}
+
+/// Behaviour that differs between interpreters, see the fields of the same
+/// names on [`VirtualMachine`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    pub rom_readonly: bool,
+    pub strict: bool,
+    pub xo_chip: bool,
+    pub stack_wrap: bool,
+    pub jump_with_vx: bool,
+    pub logic_resets_vf: bool,
+    pub load_store_increments_i: IndexIncrement,
+    pub wrap_sprites: bool,
+    pub i_overflow_sets_vf: bool,
+    pub halt_on_idle: bool,
+    pub halt_on_top_return: bool,
+}
+
+/// Instructions executed between FX15 arming the delay timer and it reaching zero
+#[derive(Default)]
+struct WaitProfile {
+    started_at: Option<u64>,
+    total: u64,
+    count: u64,
+}
+
+/// Host time spent executing DXYN and all other opcodes
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DrawTiming {
+    pub draw: Duration,
+    pub other: Duration,
+}
+
+impl DrawTiming {
+    /// Share of the time spent drawing, between 0 and 1
+    pub fn draw_share(&self) -> f64 {
+        let total = (self.draw + self.other).as_secs_f64();
+        if total > 0.0 {
+            self.draw.as_secs_f64() / total
+        } else {
+            0.0
+        }
+    }
+}
+
+#[derive(Default)]
+struct TimingProfile {
+    current: DrawTiming,
+    last_frame: DrawTiming,
+    total: DrawTiming,
+}
+
+/// Executed opcodes per class, indexed by the high nibble times 0x100 plus
+/// the sub-op, the last nibble for 8XYN and the low byte for FXNN
+type OpcodeCounts = [u64; 0x1000];
+
+/// The interpreter core. It is built from ROM bytes and never touches files
+/// or SDL, frontends drive it with `step` or `run_frame` and read back the
+/// canvas and timers.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct VirtualMachine {
+    #[cfg_attr(feature = "serde", serde(with = "memory_serde"))]
     memory: [u8; 0x1000],
-    stack: ArrayVec<u16, 100>,
+    /// Memory right after loading, restored by `reset`
+    #[cfg_attr(feature = "serde", serde(with = "memory_serde"))]
+    boot_memory: [u8; 0x1000],
+    stack: ArrayVec<u16, MAX_STACK_DEPTH>,
+    stack_depth: usize,
+    pub stack_wrap: bool,
+    /// SUPER-CHIP BXNN: offset jumps by VX instead of V0
+    pub jump_with_vx: bool,
+    /// COSMAC VIP: 8XY1, 8XY2 and 8XY3 clear VF
+    pub logic_resets_vf: bool,
+    pub load_store_increments_i: IndexIncrement,
+    /// Sprites crossing the right edge continue on the left instead of
+    /// being clipped, rows always wrap vertically
+    pub wrap_sprites: bool,
+    /// Amiga interpreter: FX1E sets VF when I leaves the address space
+    pub i_overflow_sets_vf: bool,
     registers: [u8; 16],
     i: u16,
     pc: u16,
+    /// Start of the program, 0x200 for most ROMs
+    load_address: u16,
+    /// Address of the glyph for 0, used by FX29
+    font_base: u16,
+    rom_end: u16,
+    pub rom_readonly: bool,
+    pub xo_chip: bool,
+    audio_pattern: [u8; 16],
+    pitch: u8,
+    pub strict: bool,
+    pub halt_on_top_return: bool,
+    pub halt_on_idle: bool,
+    halted: bool,
+    /// Source of CXNN, owned so that seeding one machine doesn't affect others
+    #[cfg_attr(feature = "serde", serde(with = "rng_serde"))]
+    rng: fastrand::Rng,
+    pub frame_rng_seed: Option<u64>,
+    pub profile_waits: bool,
+    /// Lets test ROMs print by writing to [`DEBUG_PORT`]
+    pub debug_port: bool,
+    /// Leaves out the warnings printed when lenient mode skips a problem
+    pub quiet: bool,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    waits: WaitProfile,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    opcode_counts: Option<Box<OpcodeCounts>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    timing: Option<TimingProfile>,
+    instruction_count: u64,
+    frame_count: u64,
+    /// `--trace` output, one line per executed instruction
+    #[cfg_attr(feature = "serde", serde(skip))]
+    trace: Option<BufWriter<Box<dyn Write>>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pre_step_hook: Option<Box<dyn FnMut(u16, u16)>>,
     pub delay_timer: u8,
     pub sound_timer: u8,
     pub pressed_key: Option<u8>,
-    pub canvas: [u64; HEIGHT],
+    /// Key pressed during FX0A, which completes once it is released
+    pending_key: Option<u8>,
+    /// Two bit-planes, classic programs only use the first one. A pixel's
+    /// color index has plane 0 in bit 0 and plane 1 in bit 1.
+    pub canvas: [Canvas; 2],
+    /// Bitmask of the planes drawn to and cleared, selected with FN01
+    selected_planes: u8,
+    /// Pixels turned off by sprite collisions during the last frame, only
+    /// tracked while highlighting collisions
+    #[cfg_attr(feature = "serde", serde(skip))]
+    collisions: Option<Canvas>,
+}
+
+/// Little-endian cursor over a save-state buffer
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.bytes.len() < len {
+            bail!("Save state is truncated");
+        }
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    pub fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into()?))
+    }
+
+    pub fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into()?))
+    }
+
+    pub fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into()?))
+    }
+}
+
+/// Collects the settings fixed at load time, for loading a ROM in one call
+#[derive(Default)]
+pub struct VirtualMachineBuilder {
+    quirks: Quirks,
+    seed: Option<u64>,
+    stack_depth: Option<usize>,
+    load_address: Option<u16>,
+    font: Option<[u8; 80]>,
+    font_address: Option<u16>,
+}
+
+impl VirtualMachineBuilder {
+    pub fn quirks(mut self, quirks: Quirks) -> Self {
+        self.quirks = quirks;
+        self
+    }
+
+    /// Seeds CXNN, the same seed gives the same random numbers
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn stack_depth(mut self, depth: usize) -> Self {
+        self.stack_depth = Some(depth);
+        self
+    }
+
+    pub fn load_address(mut self, address: u16) -> Self {
+        self.load_address = Some(address);
+        self
+    }
+
+    pub fn font(mut self, font: [u8; 80]) -> Self {
+        self.font = Some(font);
+        self
+    }
+
+    /// Where the font is loaded, 0x50 by default
+    pub fn font_address(mut self, address: u16) -> Self {
+        self.font_address = Some(address);
+        self
+    }
+
+    pub fn build_from_bytes(self, rom: &[u8]) -> Result<VirtualMachine> {
+        let load_address = self.load_address.unwrap_or(DEFAULT_LOAD_ADDRESS);
+        let font = self.font.as_ref().unwrap_or(&characters::CHARS);
+        let font_address = self.font_address.unwrap_or(FONT_BASE);
+        let mut machine = VirtualMachine::load(rom, load_address, font, font_address)?;
+
+        if self.quirks.strict {
+            if let Some(problem) = rom_problem(rom.len()) {
+                bail!(problem);
+            }
+        }
+        machine.set_quirks(self.quirks);
+
+        if let Some(depth) = self.stack_depth {
+            machine.set_stack_depth(depth)?;
+        }
+        if let Some(seed) = self.seed {
+            machine.seed_rng(seed);
+        }
+
+        Ok(machine)
+    }
 }
 
 impl VirtualMachine {
-    pub fn new(path: &Path) -> Result<Self> {
-        let rom = std::fs::read(path).with_context(|| format!("Failed to read ROM: {:?}", path))?;
+    pub fn builder() -> VirtualMachineBuilder {
+        VirtualMachineBuilder::default()
+    }
+
+    pub fn from_bytes(rom: &[u8]) -> Result<Self> {
+        Self::builder().build_from_bytes(rom)
+    }
+
+    /// Loads `opcodes` at the default load address, for unit tests
+    #[cfg(test)]
+    pub(crate) fn from_opcodes(opcodes: &[u16]) -> Self {
+        let rom: Vec<u8> = opcodes
+            .iter()
+            .flat_map(|opcode| opcode.to_be_bytes())
+            .collect();
+        Self::from_bytes(&rom).unwrap()
+    }
+
+    /// Copies the ROM to `load_address`, where execution starts
+    pub fn from_bytes_at(rom: &[u8], load_address: u16) -> Result<Self> {
+        Self::load(rom, load_address, &characters::CHARS, FONT_BASE)
+    }
+
+    /// Copies the ROM to `load_address` and the font to `font_base`
+    fn load(rom: &[u8], load_address: u16, font: &[u8; 80], font_base: u16) -> Result<Self> {
+        if load_address >= 0x1000 {
+            bail!("Load address {:#05X} is outside memory", load_address);
+        }
+        if rom.is_empty() {
+            bail!("ROM is empty");
+        }
+        if load_address as usize + rom.len() > 0x1000 {
+            bail!(
+                "ROM is too large: {} bytes at {:#05X}",
+                rom.len(),
+                load_address
+            );
+        }
+        check_font_placement(
+            font_base,
+            load_address as usize..load_address as usize + rom.len(),
+        )?;
+
         let mut machine = Self {
             memory: [0; 0x1000],
+            boot_memory: [0; 0x1000],
             stack: ArrayVec::new(),
+            stack_depth: MAX_STACK_DEPTH,
+            stack_wrap: false,
+            jump_with_vx: false,
+            logic_resets_vf: false,
+            load_store_increments_i: IndexIncrement::None,
+            wrap_sprites: false,
+            i_overflow_sets_vf: false,
             registers: [0; 16],
-            i: 0x200,
-            pc: 0x200,
+            i: load_address,
+            pc: load_address,
+            load_address,
+            font_base,
+            rom_end: load_address + rom.len() as u16,
+            rom_readonly: false,
+            xo_chip: false,
+            audio_pattern: [0; 16],
+            pitch: 64,
+            strict: false,
+            halt_on_top_return: false,
+            halt_on_idle: false,
+            halted: false,
+            rng: fastrand::Rng::new(),
+            frame_rng_seed: None,
+            profile_waits: false,
+            debug_port: false,
+            quiet: false,
+            waits: WaitProfile::default(),
+            opcode_counts: None,
+            timing: None,
+            instruction_count: 0,
+            frame_count: 0,
+            trace: None,
+            pre_step_hook: None,
             delay_timer: 0,
             sound_timer: 0,
             pressed_key: None,
-            canvas: [0; HEIGHT],
+            pending_key: None,
+            canvas: [Canvas::new(WIDTH, HEIGHT), Canvas::new(WIDTH, HEIGHT)],
+            selected_planes: 0b01,
+            collisions: None,
         };
 
-        // Game ROM starts at 0x200
-        machine.memory[0x200..(0x200 + rom.len())].copy_from_slice(&rom);
+        let start = load_address as usize;
+        machine.memory[start..start + rom.len()].copy_from_slice(rom);
 
-        // Font ROM starts at 0x50
-        machine.memory[0x50..0xA0].copy_from_slice(&characters::CHARS);
+        machine.load_font(font);
 
         Ok(machine)
     }
 
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.rom_readonly = quirks.rom_readonly;
+        self.strict = quirks.strict;
+        self.xo_chip = quirks.xo_chip;
+        self.stack_wrap = quirks.stack_wrap;
+        self.jump_with_vx = quirks.jump_with_vx;
+        self.logic_resets_vf = quirks.logic_resets_vf;
+        self.load_store_increments_i = quirks.load_store_increments_i;
+        self.wrap_sprites = quirks.wrap_sprites;
+        self.i_overflow_sets_vf = quirks.i_overflow_sets_vf;
+        self.halt_on_idle = quirks.halt_on_idle;
+        self.halt_on_top_return = quirks.halt_on_top_return;
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        Quirks {
+            rom_readonly: self.rom_readonly,
+            strict: self.strict,
+            xo_chip: self.xo_chip,
+            stack_wrap: self.stack_wrap,
+            jump_with_vx: self.jump_with_vx,
+            logic_resets_vf: self.logic_resets_vf,
+            load_store_increments_i: self.load_store_increments_i,
+            wrap_sprites: self.wrap_sprites,
+            i_overflow_sets_vf: self.i_overflow_sets_vf,
+            halt_on_idle: self.halt_on_idle,
+            halt_on_top_return: self.halt_on_top_return,
+        }
+    }
+
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.rng.seed(seed);
+    }
+
+    /// Current state of the CXNN generator, `seed_rng` with it continues the
+    /// same sequence
+    pub fn rng_seed(&self) -> u64 {
+        self.rng.get_seed()
+    }
+
+    /// Returns to the state right after loading, keeping the ROM, font and
+    /// loaded segments as well as all configuration
+    pub fn reset(&mut self) {
+        self.memory = self.boot_memory;
+        self.stack.clear();
+        self.registers = [0; 16];
+        self.i = self.load_address;
+        self.pc = self.load_address;
+        self.audio_pattern = [0; 16];
+        self.pitch = 64;
+        self.halted = false;
+        self.waits = WaitProfile::default();
+        if let Some(counts) = &mut self.opcode_counts {
+            counts.fill(0);
+        }
+        self.instruction_count = 0;
+        self.frame_count = 0;
+        self.delay_timer = 0;
+        self.sound_timer = 0;
+        self.pressed_key = None;
+        self.pending_key = None;
+        for plane in &mut self.canvas {
+            plane.clear();
+        }
+        self.selected_planes = 0b01;
+    }
+
+    /// Copies `data` into memory at `address`, e.g. for data kept apart from the program
+    pub fn load_segment(&mut self, address: u16, data: &[u8]) -> Result<()> {
+        let end = address as usize + data.len();
+        if end > self.memory.len() {
+            bail!(
+                "Segment {:#05X}..{:#05X} does not fit in memory",
+                address,
+                end
+            );
+        }
+        let font = self.font_base as usize..self.font_base as usize + FONT_SIZE;
+        if (address as usize) < font.end && font.start < end {
+            bail!(
+                "Segment {:#05X}..{:#05X} overlaps the font at {:#05X}..{:#05X}",
+                address,
+                end,
+                font.start,
+                font.end
+            );
+        }
+        self.memory[address as usize..end].copy_from_slice(data);
+        self.boot_memory = self.memory;
+        Ok(())
+    }
+
+    pub fn set_stack_depth(&mut self, depth: usize) -> Result<()> {
+        if depth == 0 || depth > MAX_STACK_DEPTH {
+            bail!(
+                "Stack depth must be between 1 and {}, got {}",
+                MAX_STACK_DEPTH,
+                depth
+            );
+        }
+        self.stack_depth = depth;
+        Ok(())
+    }
+
+    pub fn stack_depth(&self) -> usize {
+        self.stack_depth
+    }
+
+    /// Replaces the font at the current font base, 0x50 unless relocated
+    pub fn load_font(&mut self, font: &[u8; 80]) {
+        let base = self.font_base as usize;
+        self.memory[base..base + font.len()].copy_from_slice(font);
+        self.boot_memory = self.memory;
+    }
+
+    /// Swaps the font while running, the change survives a reset
+    pub fn switch_font(&mut self, font: &[u8; 80]) {
+        let base = self.font_base as usize;
+        self.memory[base..base + font.len()].copy_from_slice(font);
+        self.boot_memory[base..base + font.len()].copy_from_slice(font);
+    }
+
+    /// Loads the font at `base` instead, FX29 points into it from then on
+    pub fn load_font_at(&mut self, font: &[u8; 80], base: u16) -> Result<()> {
+        check_font_placement(base, self.load_address as usize..self.rom_end as usize)?;
+        self.font_base = base;
+        self.load_font(font);
+        Ok(())
+    }
+
+    pub fn write_state(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.memory);
+        out.extend_from_slice(&self.registers);
+        out.extend_from_slice(&self.i.to_le_bytes());
+        out.extend_from_slice(&self.pc.to_le_bytes());
+        out.extend_from_slice(&self.rom_end.to_le_bytes());
+        out.push(self.delay_timer);
+        out.push(self.sound_timer);
+        out.push(self.stack.len() as u8);
+        for address in &self.stack {
+            out.extend_from_slice(&address.to_le_bytes());
+        }
+        for word in self.canvas.iter().flat_map(Canvas::words) {
+            out.extend_from_slice(&word.to_le_bytes());
+        }
+        out.push(self.selected_planes);
+        out.extend_from_slice(&self.audio_pattern);
+        out.push(self.pitch);
+        out.push(self.pending_key.unwrap_or(0xFF));
+        out.extend_from_slice(&self.frame_count.to_le_bytes());
+    }
+
+    /// The machine is only modified once the whole state was read successfully
+    pub fn read_state(&mut self, reader: &mut Reader) -> Result<()> {
+        let memory = reader.bytes(self.memory.len())?;
+        let registers = reader.bytes(self.registers.len())?;
+        let i = reader.u16()?;
+        let pc = reader.u16()?;
+        let rom_end = reader.u16()?;
+        let delay_timer = reader.u8()?;
+        let sound_timer = reader.u8()?;
+
+        let mut stack = ArrayVec::new();
+        for _ in 0..reader.u8()? {
+            if stack.is_full() {
+                bail!("Stack overflow");
+            }
+            let address = reader.u16()?;
+            if address >= 0x1000 {
+                bail!("Save state is corrupted");
+            }
+            stack.push(address);
+        }
+
+        let mut canvas = self.canvas.clone();
+        for word in canvas.iter_mut().flat_map(Canvas::words_mut) {
+            *word = reader.u64()?;
+        }
+        let selected_planes = reader.u8()? & 0b11;
+        let audio_pattern = reader.bytes(self.audio_pattern.len())?;
+        let pitch = reader.u8()?;
+        let pending_key = match reader.u8()? {
+            0xFF => None,
+            key => Some(key & 0xF),
+        };
+        let frame_count = reader.u64()?;
+
+        if pc >= 0x1000 || i >= 0x1000 {
+            bail!("Save state is corrupted");
+        }
+
+        self.memory.copy_from_slice(memory);
+        self.registers.copy_from_slice(registers);
+        self.i = i;
+        self.pc = pc;
+        self.rom_end = rom_end;
+        self.delay_timer = delay_timer;
+        self.sound_timer = sound_timer;
+        self.stack = stack;
+        self.canvas = canvas;
+        self.selected_planes = selected_planes;
+        self.audio_pattern.copy_from_slice(audio_pattern);
+        self.pitch = pitch;
+        self.pending_key = pending_key;
+        self.frame_count = frame_count;
+
+        Ok(())
+    }
+
+    // The `safe-memory` feature trades some speed for checked indexing in
+    // release builds too, otherwise bounds are only asserted in debug builds
+
     fn get_memory(&self, address: u16) -> u8 {
+        if cfg!(feature = "safe-memory") {
+            return self.memory[address as usize];
+        }
         debug_assert!(address < 0x1000, "Address out of bounds: {:#X}", address);
         unsafe { *self.memory.get_unchecked(address as usize) }
     }
 
     fn set_memory(&mut self, address: u16, byte: u8) {
         debug_assert!(address < 0x1000, "Address out of bounds: {:#X}", address);
+        if self.rom_readonly && (self.load_address..self.rom_end).contains(&address) {
+            self.warn(format_args!(
+                "Ignored write to read-only ROM at {:#05X} (pc {:#05X})",
+                address, self.pc
+            ));
+            return;
+        }
+        if self.debug_port && DEBUG_PORT.contains(&address) {
+            eprint!("{}", byte as char);
+        }
+        if cfg!(feature = "safe-memory") {
+            self.memory[address as usize] = byte;
+            return;
+        }
         unsafe { *self.memory.get_unchecked_mut(address as usize) = byte }
     }
 
     fn get_register(&self, register: u8) -> u8 {
+        if cfg!(feature = "safe-memory") {
+            return self.registers[register as usize];
+        }
         debug_assert!(register < 0x10, "Register does not exist: {:#X}", register);
         unsafe { *self.registers.get_unchecked(register as usize) }
     }
 
     fn set_register(&mut self, register: u8, byte: u8) {
+        if cfg!(feature = "safe-memory") {
+            self.registers[register as usize] = byte;
+            return;
+        }
         debug_assert!(register < 0x10, "Register does not exist: {:#X}", register);
         unsafe { *self.registers.get_unchecked_mut(register as usize) = byte }
     }
@@ -72,34 +676,70 @@ impl VirtualMachine {
     }
 
     fn update_pc(&mut self, address: u16) {
-        let new_pc = self.get_register(0) as u16 + address;
+        let register = if self.jump_with_vx {
+            (address >> 8) as u8 & 0xF
+        } else {
+            0
+        };
+        let new_pc = (self.get_register(register) as u16 + address) & 0xFFF;
         self.pc = new_pc;
     }
 
+    // The program counter wraps around within the 4K address space like on
+    // real hardware, which also keeps every fetch in bounds
     fn inc_pc(&mut self) {
-        self.pc += 2;
+        self.pc = (self.pc + 2) & 0xFFF;
     }
 
     fn dec_pc(&mut self) {
-        self.pc -= 2;
+        self.pc = self.previous_pc();
+    }
+
+    /// Address of the opcode being executed, once the pc moved past it
+    fn previous_pc(&self) -> u16 {
+        self.pc.wrapping_sub(2) & 0xFFF
     }
 
     fn call(&mut self, address: u16) -> Result<()> {
-        if self.stack.len() >= self.stack.capacity() {
-            bail!("Stack overflow");
+        if self.stack.len() >= self.stack_depth {
+            if !self.stack_wrap {
+                bail!("Stack overflow at {:#05X}", self.previous_pc());
+            }
+            // Wrapping quirk: the oldest return address gets overwritten
+            self.stack.remove(0);
         }
         self.stack.push(self.pc);
-        self.pc = address;
+        self.pc = address & 0xFFF;
         Ok(())
     }
 
-    fn _return(&mut self) {
-        debug_assert!(!self.stack.is_empty(), "Stack underflow");
-        self.pc = self.stack.pop().unwrap();
+    fn _return(&mut self) -> Result<()> {
+        let Some(address) = self.stack.pop() else {
+            let pc = self.previous_pc();
+            if self.strict {
+                bail!("Stack underflow at {:#05X}", pc);
+            }
+            self.warn(format_args!(
+                "Skipped return with an empty stack at {:#05X}",
+                pc
+            ));
+            return Ok(());
+        };
+
+        self.pc = address & 0xFFF;
+        // An empty stack after a return usually means the program fell off its end
+        if self.halt_on_top_return && self.stack.is_empty() {
+            self.halted = true;
+        }
+        Ok(())
     }
 
     fn jump_to(&mut self, address: u16) {
-        self.pc = address;
+        // An unconditional jump to itself is how most programs finish
+        if self.halt_on_idle && address == self.previous_pc() {
+            self.halted = true;
+        }
+        self.pc = address & 0xFFF;
     }
 
     fn skip_if_byte(&mut self, register: u8, byte: u8, relation: Relation) {
@@ -142,103 +782,408 @@ impl VirtualMachine {
         }
     }
 
+    /// Repeats FX0A until a key was pressed and released again, like the
+    /// original interpreter, so a held key doesn't register repeatedly
+    fn wait_for_key(&mut self, register: u8) {
+        match self.pending_key {
+            Some(key) if self.pressed_key != Some(key) => {
+                self.set_register(register, key);
+                self.pending_key = None;
+            }
+            Some(_) => self.dec_pc(),
+            None => {
+                self.pending_key = self.pressed_key;
+                self.dec_pc();
+            }
+        }
+    }
+
     fn add_byte(&mut self, register: u8, byte: u8) {
         let value = self.get_register(register);
         self.set_register(register, value.wrapping_add(byte));
     }
 
+    /// Average number of instructions per delay timer wait and the number of
+    /// waits, if `profile_waits` recorded any
+    pub fn average_wait(&self) -> Option<(f64, u64)> {
+        (self.waits.count > 0).then(|| {
+            (
+                self.waits.total as f64 / self.waits.count as f64,
+                self.waits.count,
+            )
+        })
+    }
+
+    /// Starts tracking the pixels sprite collisions turn off, see [`Self::collisions`]
+    pub fn enable_collision_highlight(&mut self) {
+        self.collisions = Some(Canvas::new(WIDTH, HEIGHT));
+    }
+
+    /// Pixels turned off by sprite collisions during the last frame, if
+    /// tracking them is enabled
+    pub fn collisions(&self) -> Option<&Canvas> {
+        self.collisions.as_ref()
+    }
+
+    /// Starts measuring the time spent in DXYN against all other opcodes
+    pub fn enable_draw_timing(&mut self) {
+        self.timing = Some(TimingProfile::default());
+    }
+
+    /// Draw timing of the last frame and of the whole run, if enabled
+    pub fn draw_timing(&self) -> Option<(DrawTiming, DrawTiming)> {
+        self.timing
+            .as_ref()
+            .map(|timing| (timing.last_frame, timing.total))
+    }
+
+    /// Starts counting executed opcodes per class for [`Self::opcode_histogram`]
+    pub fn enable_opcode_profile(&mut self) {
+        self.opcode_counts = Some(Box::new([0; 0x1000]));
+    }
+
+    /// Executed opcode classes like `8XY4` or `FX15` with their counts, most
+    /// frequent first. Empty unless the profile is enabled.
+    pub fn opcode_histogram(&self) -> Vec<(String, u64)> {
+        let Some(counts) = &self.opcode_counts else {
+            return Vec::new();
+        };
+
+        let mut histogram: Vec<_> = counts
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(index, count)| {
+                let sub_op = index & 0xFF;
+                let class = match index >> 8 {
+                    0x0 => "0NNN".to_string(),
+                    0x1 => "1NNN".to_string(),
+                    0x2 => "2NNN".to_string(),
+                    0x3 => "3XNN".to_string(),
+                    0x4 => "4XNN".to_string(),
+                    0x5 => "5XY0".to_string(),
+                    0x6 => "6XNN".to_string(),
+                    0x7 => "7XNN".to_string(),
+                    0x8 => format!("8XY{:X}", sub_op),
+                    0x9 => "9XY0".to_string(),
+                    0xA => "ANNN".to_string(),
+                    0xB => "BNNN".to_string(),
+                    0xC => "CXNN".to_string(),
+                    0xD => "DXYN".to_string(),
+                    0xE => "EXNN".to_string(),
+                    _ => format!("FX{:02X}", sub_op),
+                };
+                (class, *count)
+            })
+            .collect();
+        histogram.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        histogram
+    }
+
+    /// Writes `value` to memory for debugging, even into a read-only ROM
+    pub fn poke_memory(&mut self, address: u16, value: u8) -> Result<()> {
+        match self.memory.get_mut(address as usize) {
+            Some(byte) => *byte = value,
+            None => bail!("Address out of bounds: {:#X}", address),
+        }
+        Ok(())
+    }
+
+    /// Sets register V`register` for debugging
+    pub fn poke_register(&mut self, register: u8, value: u8) -> Result<()> {
+        match self.registers.get_mut(register as usize) {
+            Some(byte) => *byte = value,
+            None => bail!("Register does not exist: {:#X}", register),
+        }
+        Ok(())
+    }
+
+    /// Whether FX0A got a key press and now waits for its release
+    pub fn is_waiting_for_release(&self) -> bool {
+        self.pending_key.is_some()
+    }
+
+    /// A halted machine stopped on purpose and shouldn't be run any further
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Instructions executed since loading or the last reset
+    pub fn instruction_count(&self) -> u64 {
+        self.instruction_count
+    }
+
+    /// Summary of pc, I, the registers and the innermost calls, for crash reports
+    pub fn dump_debug_state(&self) -> String {
+        let opcode = match self.memory.get(self.pc as usize..self.pc as usize + 2) {
+            Some(bytes) => format!("{:04X}", u16::from_be_bytes([bytes[0], bytes[1]])),
+            None => "----".to_string(),
+        };
+        let mut state = format!("PC: {:#05X} ({})  I: {:#05X}\n", self.pc, opcode, self.i);
+
+        for (index, value) in self.registers.iter().enumerate() {
+            let separator = if index % 8 == 7 { '\n' } else { ' ' };
+            let _ = write!(state, "V{:X}: {:02X}{}", index, value, separator);
+        }
+
+        state.push_str("Stack:");
+        for address in self.stack.iter().rev().take(4) {
+            let _ = write!(state, " {:#05X}", address);
+        }
+        if self.stack.len() > 4 {
+            let _ = write!(state, " ({} more)", self.stack.len() - 4);
+        }
+
+        state
+    }
+
+    /// Runs one 60Hz frame: ticks both timers once, then executes `cycles` opcodes.
+    /// `after_opcode` gets the address of each executed opcode and can end
+    /// the frame early by returning true.
+    pub fn run_frame(
+        &mut self,
+        cycles: u32,
+        mut after_opcode: impl FnMut(&Self, u16) -> bool,
+    ) -> Result<()> {
+        // Reseeding per frame makes CXNN depend only on the frame number
+        // and the position of the instruction within it
+        if let Some(seed) = self.frame_rng_seed {
+            self.rng
+                .seed(seed ^ self.frame_count.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+        }
+        self.frame_count += 1;
+        if let Some(collisions) = &mut self.collisions {
+            collisions.clear();
+        }
+
+        if self.delay_timer == 1 {
+            if let Some(started_at) = self.waits.started_at.take() {
+                self.waits.total += self.instruction_count - started_at;
+                self.waits.count += 1;
+            }
+        }
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.sound_timer = self.sound_timer.saturating_sub(1);
+
+        let start = self.timing.is_some().then(Instant::now);
+        for _ in 0..cycles {
+            let pc = self.pc;
+            self.execute_opcode()?;
+            if after_opcode(self, pc) || self.halted {
+                break;
+            }
+        }
+
+        if let (Some(timing), Some(start)) = (&mut self.timing, start) {
+            let mut frame = std::mem::take(&mut timing.current);
+            frame.other = start.elapsed().saturating_sub(frame.draw);
+            timing.total.draw += frame.draw;
+            timing.total.other += frame.other;
+            timing.last_frame = frame;
+        }
+
+        Ok(())
+    }
+
+    /// Calls `hook` with the pc and opcode before every executed instruction,
+    /// e.g. to record the execution path for coverage-guided fuzzing
+    pub fn set_pre_step_hook(&mut self, hook: Box<dyn FnMut(u16, u16)>) {
+        self.pre_step_hook = Some(hook);
+    }
+
+    /// Writes the pc, opcode, registers, I and stack depth after every
+    /// executed instruction to `trace`
+    pub fn set_trace(&mut self, trace: Box<dyn Write>) {
+        self.trace = Some(BufWriter::new(trace));
+    }
+
+    /// Writes out any buffered `--trace` lines
+    pub fn flush_trace(&mut self) -> Result<()> {
+        if let Some(trace) = &mut self.trace {
+            trace.flush().context("Failed to write trace")?;
+        }
+        Ok(())
+    }
+
+    /// Executes a single instruction
+    pub fn step(&mut self) -> Result<()> {
+        self.execute_opcode()
+    }
+
     /// Source: https://en.wikipedia.org/wiki/CHIP-8#Opcode_table
     pub fn execute_opcode(&mut self) -> Result<()> {
-        let (byte1, byte2) = (self.get_memory(self.pc), self.get_memory(self.pc + 1));
+        let (byte1, byte2) = (
+            self.get_memory(self.pc),
+            self.get_memory((self.pc + 1) & 0xFFF),
+        );
+
+        if let Some(hook) = &mut self.pre_step_hook {
+            hook(self.pc, u16::from_be_bytes([byte1, byte2]));
+        }
 
-        let address = ((byte1 as u16 & 0x0F) << 8) | (byte2 as u16);
-        let register_x = byte1 & 0x0F;
-        let register_y = byte2 >> 4;
+        let opcode = u16::from_be_bytes([byte1, byte2]);
         let last_nibble = byte2 & 0x0F;
 
+        let pc = self.pc;
         self.inc_pc();
+        self.instruction_count += 1;
+        if let Some(counts) = &mut self.opcode_counts {
+            let sub_op = match byte1 >> 4 {
+                0x8 => last_nibble,
+                0xF => byte2,
+                _ => 0,
+            };
+            counts[((byte1 as usize >> 4) << 8) | sub_op as usize] += 1;
+        }
 
-        match (byte1 & 0xF0) >> 4 {
-            0x0 => match byte2 {
-                0xE0 => self.clear_canvas(),
-                0xEE => self._return(),
-                _ => self.call(address)?,
-            },
-            0x1 => self.jump_to(address),
-            0x2 => self.call(address)?,
-            0x3 => self.skip_if_byte(register_x, byte2, Relation::Equal),
-            0x4 => self.skip_if_byte(register_x, byte2, Relation::NotEqual),
-            0x5 => {
-                if last_nibble != 0 {
-                    bail!("Invalid opcode: {:02X}{:02X}", byte1, byte2);
-                }
-                self.skip_if_register(register_x, register_y, Relation::Equal);
+        match DecodedOp::decode(opcode, self.xo_chip) {
+            DecodedOp::ClearScreen => self.clear_canvas(),
+            DecodedOp::Return => self._return()?,
+            DecodedOp::MachineCall(address) => self.machine_call(address)?,
+            DecodedOp::Jump(address) => self.jump_to(address),
+            DecodedOp::Call(address) => self.call(address)?,
+            DecodedOp::SkipIfEqual { x, byte } => self.skip_if_byte(x, byte, Relation::Equal),
+            DecodedOp::SkipIfNotEqual { x, byte } => self.skip_if_byte(x, byte, Relation::NotEqual),
+            DecodedOp::SkipIfRegistersEqual { x, y } => {
+                self.skip_if_register(x, y, Relation::Equal)
             }
-            0x6 => self.set_register(register_x, byte2),
-            0x7 => self.add_byte(register_x, byte2),
-            0x8 => self.execute_math(last_nibble, register_x, register_y)?,
-            0x9 => {
-                if last_nibble != 0 {
-                    bail!("Invalid opcode: {:02X}{:02X}", byte1, byte2);
-                }
-                self.skip_if_register(register_x, register_y, Relation::NotEqual);
+            DecodedOp::Load { x, byte } => self.set_register(x, byte),
+            DecodedOp::AddByte { x, byte } => self.add_byte(x, byte),
+            DecodedOp::Copy { x, y }
+            | DecodedOp::Or { x, y }
+            | DecodedOp::And { x, y }
+            | DecodedOp::Xor { x, y }
+            | DecodedOp::Add { x, y }
+            | DecodedOp::Sub { x, y }
+            | DecodedOp::ShiftRight { x, y }
+            | DecodedOp::SubReverse { x, y }
+            | DecodedOp::ShiftLeft { x, y } => self.execute_math(last_nibble, x, y)?,
+            DecodedOp::SkipIfRegistersNotEqual { x, y } => {
+                self.skip_if_register(x, y, Relation::NotEqual)
             }
-            0xA => self.i = address,
-            0xB => self.update_pc(address),
-            0xC => self.set_register(register_x, fastrand::u8(..) & byte2),
-            0xD => {
-                let x = self.get_register(register_x);
-                let y = self.get_register(register_y);
-                let height = last_nibble;
-
-                self.draw(x, y, height);
+            DecodedOp::LoadI(address) => self.i = address,
+            DecodedOp::JumpOffset(address) => self.update_pc(address),
+            DecodedOp::Random { x, mask } => {
+                let random = self.rng.u8(..);
+                self.set_register(x, random & mask);
             }
-            0xE => match byte2 {
-                0x9E => self.skip_if_key(register_x, Relation::Equal),
-                0xA1 => self.skip_if_key(register_x, Relation::NotEqual),
-                _ => bail!("Invalid opcode: {:02X}{:02X}", byte1, byte2),
-            },
-            0xF => match byte2 {
-                0x07 => self.set_register(register_x, self.delay_timer),
-                0x0A => {
-                    let value = self.pressed_key.take();
-
-                    if let Some(code) = value {
-                        self.set_register(register_x, code);
-                    } else {
-                        self.dec_pc();
-                    }
+            DecodedOp::Draw { x, y, height } => {
+                let x = self.get_register(x);
+                let y = self.get_register(y);
+
+                let start = self.timing.is_some().then(Instant::now);
+                self.draw(x, y, height)?;
+                if let (Some(timing), Some(start)) = (&mut self.timing, start) {
+                    timing.current.draw += start.elapsed();
                 }
-                0x15 => self.delay_timer = self.get_register(register_x),
-                0x18 => {
-                    self.sound_timer = self.get_register(register_x);
-                    // SDL doesnt alway play audio if it only lasts for 1 frame
-                    if self.sound_timer < 2 {
-                        self.sound_timer = 2;
-                    }
+            }
+            DecodedOp::SkipIfKey(x) => self.skip_if_key(x, Relation::Equal),
+            DecodedOp::SkipIfNotKey(x) => self.skip_if_key(x, Relation::NotEqual),
+            DecodedOp::SelectPlanes(planes) => self.selected_planes = planes,
+            DecodedOp::LoadAudioPattern => self.load_audio_pattern(),
+            DecodedOp::ReadDelayTimer(x) => self.set_register(x, self.delay_timer),
+            DecodedOp::WaitForKey(x) => self.wait_for_key(x),
+            DecodedOp::SetDelayTimer(x) => {
+                self.delay_timer = self.get_register(x);
+                if self.profile_waits && self.delay_timer > 0 {
+                    self.waits.started_at = Some(self.instruction_count);
                 }
-                0x1E => self.i += self.get_register(register_x) as u16,
-                0x29 => self.i = 0x50 + self.get_register(register_x) as u16 * 5,
-                0x33 => self.set_bcd(register_x),
-                0x55 => self.dump_registers(register_x),
-                0x65 => self.load_registers(register_x),
-                _ => bail!("Invalid opcode: {:02X}{:02X}", byte1, byte2),
-            },
-            _ => bail!("Invalid opcode: {:02X}{:02X}", byte1, byte2),
+            }
+            DecodedOp::SetSoundTimer(x) => self.sound_timer = self.get_register(x),
+            DecodedOp::AddToI(x) => self.add_to_i(x),
+            DecodedOp::LoadFont(x) => {
+                self.i = (self.font_base + self.get_register(x) as u16 * 5) & 0xFFF
+            }
+            DecodedOp::StoreBcd(x) => self.set_bcd(x),
+            DecodedOp::SetPitch(x) => self.pitch = self.get_register(x),
+            DecodedOp::StoreRegisters(x) => self.dump_registers(x),
+            DecodedOp::LoadRegisters(x) => self.load_registers(x),
+            DecodedOp::Unknown(opcode) => self.invalid_opcode(opcode)?,
+        }
+
+        if let Some(trace) = &mut self.trace {
+            write!(trace, "{:03X} {:02X}{:02X} V:", pc, byte1, byte2)?;
+            for value in self.registers {
+                write!(trace, "{:02X}", value)?;
+            }
+            writeln!(trace, " I:{:03X} SP:{:02X}", self.i, self.stack.len())?;
         }
 
         Ok(())
     }
 
+    /// 0NNN runs native COSMAC VIP machine code, which can't be emulated,
+    /// so it is a NOP unless running in strict mode
+    fn machine_call(&self, address: u16) -> Result<()> {
+        if self.strict {
+            bail!(
+                "Unsupported machine code call to {:#05X} at {:#05X}",
+                address,
+                self.previous_pc()
+            );
+        }
+        Ok(())
+    }
+
+    fn warn(&self, message: std::fmt::Arguments) {
+        if !self.quiet {
+            eprintln!("{}", message);
+        }
+    }
+
+    /// Invalid opcodes are skipped like a NOP, unless running in strict mode
+    fn invalid_opcode(&self, opcode: u16) -> Result<()> {
+        let pc = self.previous_pc();
+        if self.strict {
+            bail!("Invalid opcode: {:04X} at {:#05X}", opcode, pc);
+        }
+        self.warn(format_args!(
+            "Skipped invalid opcode {:04X} at {:#05X}",
+            opcode, pc
+        ));
+        Ok(())
+    }
+
+    fn add_to_i(&mut self, register: u8) {
+        let sum = self.i + self.get_register(register) as u16;
+        if self.i_overflow_sets_vf {
+            self.set_flag((sum > 0xFFF) as u8);
+        }
+        self.i = sum & 0xFFF;
+    }
+
+    /// Address `offset` bytes past I, wrapping around within memory like the pc
+    fn address_from_i(&self, offset: u16) -> u16 {
+        (self.i + offset) & 0xFFF
+    }
+
     fn dump_registers(&mut self, register: u8) {
         for index in 0u8..=register {
-            self.set_memory(self.i + index as u16, self.get_register(index));
+            self.set_memory(self.address_from_i(index as u16), self.get_register(index));
         }
+        self.increment_i(register);
     }
 
     fn load_registers(&mut self, register: u8) {
         for index in 0u8..=register {
-            self.set_register(index, self.get_memory(self.i + index as u16));
+            self.set_register(index, self.get_memory(self.address_from_i(index as u16)));
+        }
+        self.increment_i(register);
+    }
+
+    fn increment_i(&mut self, register: u8) {
+        let increment = match self.load_store_increments_i {
+            IndexIncrement::None => 0,
+            IndexIncrement::ByX => register as u16,
+            IndexIncrement::ByXPlusOne => register as u16 + 1,
+        };
+        self.i = self.address_from_i(increment);
+    }
+
+    fn load_audio_pattern(&mut self) {
+        for index in 0..16 {
+            self.audio_pattern[index] = self.get_memory(self.address_from_i(index as u16));
         }
     }
 
@@ -251,8 +1196,8 @@ impl VirtualMachine {
         let hundreds = value;
 
         self.set_memory(self.i, hundreds);
-        self.set_memory(self.i + 1, tens);
-        self.set_memory(self.i + 2, units);
+        self.set_memory(self.address_from_i(1), tens);
+        self.set_memory(self.address_from_i(2), units);
     }
 
     fn execute_math(&mut self, operation: u8, register_x: u8, register_y: u8) -> Result<()> {
@@ -287,34 +1232,758 @@ impl VirtualMachine {
                 self.set_flag(value_x >> 7);
                 value_x << 1
             }
-            _ => bail!("Invalid operation: {:02X}", operation),
+            _ => {
+                let opcode = 0x8000
+                    | ((register_x as u16) << 8)
+                    | ((register_y as u16) << 4)
+                    | operation as u16;
+                return self.invalid_opcode(opcode);
+            }
         };
 
         self.set_register(register_x, result);
+        if self.logic_resets_vf && matches!(operation, 0x1..=0x3) {
+            self.set_flag(0);
+        }
 
         Ok(())
     }
 
+    /// Clears the selected planes only
     pub fn clear_canvas(&mut self) {
-        self.canvas.fill(0);
+        for (index, plane) in self.canvas.iter_mut().enumerate() {
+            if self.selected_planes & (1 << index) != 0 {
+                plane.clear();
+            }
+        }
     }
 
-    fn draw(&mut self, x: u8, y: u8, height: u8) {
+    /// XORs the sprite at I into every selected plane. With both planes
+    /// selected, the sprite data for plane 1 follows the data for plane 0.
+    /// Sprite rows past 0xFFF wrap to the start of memory, unless running
+    /// in strict mode
+    fn draw(&mut self, x: u8, y: u8, height: u8) -> Result<()> {
+        let planes = self.selected_planes.count_ones() as u16;
+        let end = self.i + planes * height as u16;
+        if end > 0x1000 {
+            let pc = self.previous_pc();
+            if self.strict {
+                bail!(
+                    "Sprite of {} rows at I = {:#05X} reads past 0xFFF at {:#05X}",
+                    height,
+                    self.i,
+                    pc
+                );
+            }
+            self.warn(format_args!(
+                "Wrapped sprite of {} rows at I = {:#05X} past 0xFFF at {:#05X}",
+                height, self.i, pc
+            ));
+        }
+
         let mut collision = false;
-        for dy in 0..height {
-            let byte = (self.get_memory(self.i + dy as u16).reverse_bits() as u64) << x;
+        let mut address = self.i;
+        for plane in 0..self.canvas.len() {
+            if self.selected_planes & (1 << plane) == 0 {
+                continue;
+            }
 
-            let canvas_row = &mut self.canvas[y.wrapping_add(dy) as usize % HEIGHT];
+            let before = self
+                .collisions
+                .is_some()
+                .then(|| self.canvas[plane].clone());
 
-            let mask = byte & *canvas_row;
+            // The starting position always wraps, rows past the bottom edge too
+            let x = x as usize % self.canvas[plane].width();
+            for dy in 0..height {
+                let byte = self.get_memory((address + dy as u16) & 0xFFF);
+                let y = (y as usize + dy as usize) % self.canvas[plane].height();
+                collision |= self.canvas[plane].xor_row(x, y, byte, self.wrap_sprites);
+            }
 
-            if mask != 0 {
-                collision = true;
+            if let (Some(collisions), Some(before)) = (&mut self.collisions, before) {
+                collisions.mark_turned_off(&before, &self.canvas[plane]);
             }
 
-            *canvas_row ^= byte;
+            address += height as u16;
         }
 
         self.set_flag(collision as u8);
+        Ok(())
+    }
+}
+
+/// Read-only accessors for debuggers and other tooling built on top of the VM
+impl VirtualMachine {
+    pub fn read_memory(&self, address: u16) -> Result<u8> {
+        match self.memory.get(address as usize) {
+            Some(&byte) => Ok(byte),
+            None => bail!("Address out of bounds: {:#X}", address),
+        }
+    }
+
+    pub fn memory_slice(&self, start: u16, len: u16) -> Result<&[u8]> {
+        let end = start as usize + len as usize;
+        match self.memory.get(start as usize..end) {
+            Some(slice) => Ok(slice),
+            None => bail!("Memory range out of bounds: {:#X}..{:#X}", start, end),
+        }
+    }
+
+    pub fn memory_image(&self) -> &[u8; 0x1000] {
+        &self.memory
+    }
+
+    /// Every two-byte word of the loaded program with its address, decoded
+    /// the way `execute_opcode` would run it
+    pub fn decode_all(&self) -> impl Iterator<Item = (u16, DecodedOp)> + '_ {
+        (self.load_address..self.rom_end.min(0xFFF))
+            .step_by(2)
+            .map(|address| {
+                let opcode = u16::from_be_bytes([
+                    self.memory[address as usize],
+                    self.memory[address as usize + 1],
+                ]);
+                (address, DecodedOp::decode(opcode, self.xo_chip))
+            })
+    }
+
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.registers
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn i(&self) -> u16 {
+        self.i
+    }
+
+    pub fn load_address(&self) -> u16 {
+        self.load_address
+    }
+
+    /// End of the program loaded at `load_address`, exclusive
+    pub fn rom_end(&self) -> u16 {
+        self.rom_end
+    }
+
+    /// XO-CHIP audio pattern buffer, loaded by F002
+    pub fn audio_pattern(&self) -> &[u8; 16] {
+        &self.audio_pattern
+    }
+
+    /// XO-CHIP playback pitch, set by FX3A
+    pub fn pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    pub fn canvas(&self) -> &[Canvas; 2] {
+        &self.canvas
+    }
+
+    /// The only input to audio gating: true from the FX18 that sets the sound
+    /// timer until the frame tick that brings it to zero
+    pub fn is_beeping(&self) -> bool {
+        self.sound_timer > 0
+    }
+
+    /// Renders the canvas as tightly packed RGBA pixels, `scale`×`scale` per
+    /// CHIP-8 pixel, for frontends that want an image rather than bits.
+    /// Pixels set in either plane use `fg`.
+    pub fn render_to_buffer(&self, scale: u32, fg: (u8, u8, u8), bg: (u8, u8, u8)) -> Vec<u8> {
+        let on = [fg.0, fg.1, fg.2, 0xFF];
+        let off = [bg.0, bg.1, bg.2, 0xFF];
+        canvas::render_rgba(&self.canvas, scale as usize, &[off, on, on, on])
+    }
+}
+
+/// Serde only supports arrays up to 32 elements, so memory goes through a slice
+#[cfg(feature = "serde")]
+mod memory_serde {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        memory: &[u8; 0x1000],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(memory)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<[u8; 0x1000], D::Error> {
+        let memory = Vec::<u8>::deserialize(deserializer)?;
+        let len = memory.len();
+        memory
+            .try_into()
+            .map_err(|_| D::Error::invalid_length(len, &"4096 bytes of memory"))
+    }
+}
+
+#[cfg(feature = "serde")]
+mod rng_serde {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(rng: &fastrand::Rng, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(rng.get_seed())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<fastrand::Rng, D::Error> {
+        u64::deserialize(deserializer).map(fastrand::Rng::with_seed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Loads `opcodes` with `quirks` and executes each of them once
+    fn run_program(opcodes: &[u16], quirks: Quirks) -> VirtualMachine {
+        try_run_program(opcodes, quirks).unwrap()
+    }
+
+    /// Like `run_program`, but returns the first error instead of panicking
+    fn try_run_program(opcodes: &[u16], quirks: Quirks) -> Result<VirtualMachine> {
+        let rom: Vec<u8> = opcodes
+            .iter()
+            .flat_map(|opcode| opcode.to_be_bytes())
+            .collect();
+        let mut machine = VirtualMachine::builder()
+            .quirks(quirks)
+            .build_from_bytes(&rom)?;
+        for _ in opcodes {
+            machine.step()?;
+        }
+        Ok(machine)
+    }
+
+    #[test]
+    fn bcd_writes_hundreds_tens_and_units() {
+        // VA = 123, I = 0x300, FA33
+        let machine = run_program(&[0x6A7B, 0xA300, 0xFA33], Quirks::default());
+        assert_eq!(machine.memory[0x300..0x303], [1, 2, 3]);
+    }
+
+    #[test]
+    fn subtraction_clears_vf_on_underflow() {
+        // V0 = 1, V1 = 2, V0 -= V1
+        let machine = run_program(&[0x6001, 0x6102, 0x8015], Quirks::default());
+        assert_eq!(machine.registers[0], 0xFF);
+        assert_eq!(machine.registers[0xF], 0);
+
+        // V0 = 2, V1 = 1, V0 -= V1
+        let machine = run_program(&[0x6002, 0x6101, 0x8015], Quirks::default());
+        assert_eq!(machine.registers[0], 1);
+        assert_eq!(machine.registers[0xF], 1);
+    }
+
+    #[test]
+    fn load_store_leaves_i_per_mode() {
+        // I = 0x300, store V0..V3, load V0..V3
+        let program = [0xA300, 0xF355, 0xF365];
+        for (load_store_increments_i, expected) in [
+            (IndexIncrement::None, 0x300),
+            (IndexIncrement::ByX, 0x306),
+            (IndexIncrement::ByXPlusOne, 0x308),
+        ] {
+            let quirks = Quirks {
+                load_store_increments_i,
+                ..Quirks::default()
+            };
+            let machine = run_program(&program, quirks);
+            assert_eq!(machine.i, expected, "{:?}", load_store_increments_i);
+        }
+    }
+
+    #[test]
+    fn addresses_from_i_wrap_past_the_end_of_memory() {
+        // V0..V3 = 1..4, I = 0xFFE, store V0..V3
+        let quirks = Quirks {
+            load_store_increments_i: IndexIncrement::ByXPlusOne,
+            ..Quirks::default()
+        };
+        let machine = run_program(&[0x6001, 0x6102, 0x6203, 0x6304, 0xAFFE, 0xF355], quirks);
+        assert_eq!(machine.memory[0xFFE..], [1, 2]);
+        assert_eq!(machine.memory[..2], [3, 4]);
+        assert_eq!(machine.i, 0x002);
+
+        // BCD of V0 = 1 at I = 0xFFF
+        let machine = run_program(&[0x6001, 0xAFFF, 0xF033], Quirks::default());
+        assert_eq!(machine.memory[0xFFF], 0);
+        assert_eq!(machine.memory[..2], [0, 1]);
+    }
+
+    #[test]
+    fn builder_seed_only_affects_its_own_machine() {
+        let random = |seed| {
+            let rom = [0xC0, 0xFF];
+            let mut machine = VirtualMachine::builder()
+                .seed(seed)
+                .build_from_bytes(&rom)
+                .unwrap();
+            machine.step().unwrap();
+            machine.registers[0]
+        };
+        let first = random(7);
+        fastrand::seed(1);
+        assert_eq!(random(7), first);
+    }
+
+    #[test]
+    fn builder_applies_typed_quirks() {
+        let quirks = Quirks {
+            jump_with_vx: true,
+            load_store_increments_i: IndexIncrement::ByX,
+            ..Quirks::default()
+        };
+        let machine = VirtualMachine::builder()
+            .quirks(quirks)
+            .build_from_bytes(&[0x00, 0xE0])
+            .unwrap();
+        assert!(machine.jump_with_vx);
+        assert_eq!(machine.load_store_increments_i, IndexIncrement::ByX);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn deserialized_machine_resets_to_its_boot_memory() {
+        let mut machine = run_program(&[0x60AB, 0xA300, 0xF055], Quirks::default());
+        let json = serde_json::to_string(&machine).unwrap();
+        let mut restored: VirtualMachine = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.memory[0x300], 0xAB);
+
+        restored.reset();
+        machine.reset();
+        assert_eq!(restored.memory, machine.memory);
+        assert_eq!(restored.memory[0x300], 0);
+    }
+
+    #[test]
+    fn decode_all_walks_the_program_from_the_load_address() {
+        let mut machine = VirtualMachine::from_opcodes(&[0x00E0, 0x8124, 0xF201, 0x5121]);
+        let ops: Vec<_> = machine.decode_all().collect();
+        assert_eq!(
+            ops,
+            [
+                (0x200, DecodedOp::ClearScreen),
+                (0x202, DecodedOp::Add { x: 1, y: 2 }),
+                (0x204, DecodedOp::Unknown(0xF201)),
+                (0x206, DecodedOp::Unknown(0x5121)),
+            ]
+        );
+
+        machine.xo_chip = true;
+        assert_eq!(
+            machine.decode_all().nth(2),
+            Some((0x204, DecodedOp::SelectPlanes(2)))
+        );
+    }
+
+    #[test]
+    fn fx29_points_into_a_relocated_font() {
+        // V0 = 7, I = glyph of V0
+        let rom = [0x60, 0x07, 0xF0, 0x29];
+        let mut machine = VirtualMachine::builder()
+            .font_address(0x100)
+            .build_from_bytes(&rom)
+            .unwrap();
+        machine.step().unwrap();
+        machine.step().unwrap();
+
+        assert_eq!(machine.i, 0x100 + 7 * 5);
+        let glyph = &characters::CHARS[7 * 5..8 * 5];
+        assert_eq!(machine.memory[machine.i as usize..][..5], *glyph);
+    }
+
+    #[test]
+    fn odd_length_roms_only_fail_to_load_in_strict_mode() {
+        let rom = [0x00, 0xE0, 0x12];
+        assert!(VirtualMachine::from_bytes(&rom).is_ok());
+        let strict = Quirks {
+            strict: true,
+            ..Quirks::default()
+        };
+        let machine = VirtualMachine::builder()
+            .quirks(strict)
+            .build_from_bytes(&rom);
+        assert!(machine.is_err());
+    }
+
+    #[test]
+    fn font_must_not_overlap_the_rom_or_segments() {
+        let rom = [0x00, 0xE0];
+        assert!(VirtualMachine::from_bytes_at(&rom, 0x9E).is_err());
+        assert!(VirtualMachine::from_bytes_at(&rom, 0xA0).is_ok());
+        let font_in_rom = VirtualMachine::builder()
+            .font_address(0x1FF)
+            .build_from_bytes(&rom);
+        assert!(font_in_rom.is_err());
+
+        // Once the font moved, the ROM may load where it used to be
+        let mut machine = VirtualMachine::builder()
+            .load_address(0x50)
+            .font_address(0x300)
+            .build_from_bytes(&rom)
+            .unwrap();
+        assert_eq!(machine.memory[0x50..0x52], rom);
+        assert_eq!(machine.memory[0x300..0x350], characters::CHARS);
+
+        assert!(machine.load_segment(0x340, &[0; 0x20]).is_err());
+        assert!(machine.load_segment(0x350, &[0; 0x20]).is_ok());
+        assert!(machine.load_font_at(&characters::CHARS, 0x10).is_err());
+        assert!(machine.load_font_at(&characters::CHARS, 0x00).is_ok());
+    }
+
+    #[test]
+    fn pre_step_hook_sees_every_pc_and_opcode_once() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        // V0 = 1, jump over the next opcode
+        let mut machine = VirtualMachine::from_opcodes(&[0x6001, 0x1206, 0x00E0, 0x7002]);
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let recorder = Rc::clone(&seen);
+        machine.set_pre_step_hook(Box::new(move |pc, opcode| {
+            recorder.borrow_mut().push((pc, opcode))
+        }));
+        for _ in 0..3 {
+            machine.step().unwrap();
+        }
+
+        assert_eq!(
+            *seen.borrow(),
+            [(0x200, 0x6001), (0x202, 0x1206), (0x206, 0x7002)]
+        );
+    }
+
+    #[test]
+    fn memory_accessors_check_bounds() {
+        let machine = VirtualMachine::from_opcodes(&[0x1234]);
+        assert_eq!(machine.read_memory(0x201).unwrap(), 0x34);
+        assert_eq!(machine.memory_slice(0x200, 2).unwrap(), [0x12, 0x34]);
+        assert_eq!(machine.memory_slice(0xFFE, 2).unwrap().len(), 2);
+        assert!(machine.read_memory(0x1000).is_err());
+        assert!(machine.memory_slice(0xFFF, 2).is_err());
+    }
+
+    #[test]
+    fn random_never_sets_bits_outside_the_mask() {
+        for seed in 0..64 {
+            for mask in [0x00u16, 0x01, 0x0F, 0x5A, 0x80, 0xFF] {
+                let mut machine = VirtualMachine::from_opcodes(&[0xC300 | mask]);
+                machine.seed_rng(seed);
+                machine.step().unwrap();
+                assert_eq!(
+                    machine.registers[3] & !(mask as u8),
+                    0,
+                    "seed {} mask {:#04X}",
+                    seed,
+                    mask
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn clear_screen_only_clears_the_selected_plane() {
+        // Select plane 1, clear
+        let mut machine = VirtualMachine::from_opcodes(&[0xF201, 0x00E0]);
+        machine.xo_chip = true;
+        machine.canvas[0].set_pixel(3, 4, true);
+        machine.canvas[1].set_pixel(5, 6, true);
+        machine.step().unwrap();
+        machine.step().unwrap();
+
+        assert!(machine.canvas[0].get_pixel(3, 4));
+        assert!(!machine.canvas[1].get_pixel(5, 6));
+    }
+
+    #[test]
+    fn overlapping_sprites_at_the_right_edge_clip_or_wrap() {
+        // Draw a row of 8 pixels at x = 60, then again at x = 62
+        let program = [
+            0x603C, 0x6100, 0xA20E, 0xD011, 0x603E, 0xD011, 0x120C, 0xFF00,
+        ];
+        for wrap_sprites in [false, true] {
+            let quirks = Quirks {
+                wrap_sprites,
+                ..Quirks::default()
+            };
+            let machine = run_program(&program, quirks);
+
+            let row: Vec<_> = (0..WIDTH)
+                .map(|x| machine.canvas[0].get_pixel(x, 0))
+                .collect();
+            assert_eq!(machine.registers[0xF], 1, "wrap {}", wrap_sprites);
+            assert!(row[60] && row[61] && !row[62] && !row[63]);
+            if wrap_sprites {
+                assert!(!row[0] && !row[3] && row[4] && row[5] && !row[6]);
+            } else {
+                assert!(row[..60].iter().all(|&on| !on));
+            }
+        }
+    }
+
+    #[test]
+    fn pc_wraps_to_zero_after_a_program_ending_at_the_top_of_memory() {
+        let mut machine = VirtualMachine::from_bytes_at(&[0x60, 0x05, 0x70, 0x01], 0xFFC).unwrap();
+        machine.step().unwrap();
+        machine.step().unwrap();
+        assert_eq!(machine.registers[0], 6);
+        assert_eq!(machine.pc, 0x000);
+
+        // Memory at 0 holds 0000, a machine code call that is skipped
+        machine.step().unwrap();
+        assert_eq!(machine.pc, 0x002);
+    }
+
+    #[test]
+    fn loading_rejects_addresses_outside_memory_and_empty_roms() {
+        assert!(VirtualMachine::from_bytes_at(&[], 0x1000).is_err());
+        assert!(VirtualMachine::from_bytes_at(&[0x00, 0xE0], 0x1000).is_err());
+        assert!(VirtualMachine::from_bytes_at(&[], DEFAULT_LOAD_ADDRESS).is_err());
+        assert!(VirtualMachine::from_bytes_at(&[0x00, 0xE0], 0xFFE).is_ok());
+    }
+
+    #[test]
+    fn tall_sprite_near_the_end_of_memory_wraps_or_errors_in_strict_mode() {
+        // Store V0 = 0x80 to the 15 bytes from I = 0xFF5, which wraps to
+        // 0x000..0x004, then draw them at 0, 0
+        let mut program = vec![0x6080, 0xAFF5];
+        program.extend([0xF055; 15]);
+        program.extend([0xAFF5, 0xD00F]);
+        let lenient = Quirks {
+            load_store_increments_i: IndexIncrement::ByXPlusOne,
+            ..Quirks::default()
+        };
+
+        let machine = run_program(&program, lenient);
+        assert!((0..15).all(|y| machine.canvas[0].get_pixel(0, y)));
+
+        let strict = Quirks {
+            strict: true,
+            ..lenient
+        };
+        assert!(try_run_program(&program, strict).is_err());
+    }
+
+    #[test]
+    fn return_with_an_empty_stack_is_skipped_unless_strict() {
+        let machine = run_program(&[0x00EE], Quirks::default());
+        assert_eq!(machine.pc, 0x202);
+
+        let strict = Quirks {
+            strict: true,
+            ..Quirks::default()
+        };
+        assert!(try_run_program(&[0x00EE], strict).is_err());
+    }
+
+    #[test]
+    fn jump_with_offset_uses_v0_or_vx_and_wraps() {
+        // V0 = 0x10, V2 = 0x20, jump to 0x230 plus the offset
+        let program = [0x6010, 0x6220, 0xB230];
+        for (jump_with_vx, expected) in [(false, 0x240), (true, 0x250)] {
+            let quirks = Quirks {
+                jump_with_vx,
+                ..Quirks::default()
+            };
+            let machine = run_program(&program, quirks);
+            assert_eq!(machine.pc, expected, "jump with VX {}", jump_with_vx);
+        }
+
+        // 0xFF + 0xFFF and 0x10 + 0xFF0 both carry past 0xFFF
+        let machine = run_program(&[0x60FF, 0xBFFF], Quirks::default());
+        assert_eq!(machine.pc, 0x0FE);
+        let quirks = Quirks {
+            jump_with_vx: true,
+            ..Quirks::default()
+        };
+        let machine = run_program(&[0x6F10, 0xBFF0], quirks);
+        assert_eq!(machine.pc, 0x000);
+    }
+
+    #[test]
+    fn logic_ops_clear_vf_with_the_quirk() {
+        // VF = 1, V0 |= V1
+        let program = [0x6F01, 0x6001, 0x6102, 0x8011];
+        for (logic_resets_vf, expected) in [(false, 1), (true, 0)] {
+            let quirks = Quirks {
+                logic_resets_vf,
+                ..Quirks::default()
+            };
+            let machine = run_program(&program, quirks);
+            assert_eq!(machine.registers[0], 3);
+            assert_eq!(machine.registers[0xF], expected);
+        }
+    }
+
+    #[test]
+    fn adding_to_i_past_the_end_of_memory_masks_i_and_sets_vf_with_the_quirk() {
+        // I = 0xFF0, V0 = 0x20, I += V0
+        let program = [0xAFF0, 0x6020, 0xF01E];
+        for (i_overflow_sets_vf, expected) in [(false, 0), (true, 1)] {
+            let quirks = Quirks {
+                i_overflow_sets_vf,
+                ..Quirks::default()
+            };
+            let machine = run_program(&program, quirks);
+            assert_eq!(machine.i, 0x010);
+            assert_eq!(machine.registers[0xF], expected);
+        }
+
+        // VF = 1, I = 0x300, V0 = 0x20, I += V0 clears VF
+        let quirks = Quirks {
+            i_overflow_sets_vf: true,
+            ..Quirks::default()
+        };
+        let machine = run_program(&[0x6F01, 0xA300, 0x6020, 0xF01E], quirks);
+        assert_eq!(machine.i, 0x320);
+        assert_eq!(machine.registers[0xF], 0);
+    }
+
+    #[test]
+    fn is_beeping_follows_the_sound_timer() {
+        // Sound timer = 2, then idle
+        let mut machine = run_program(&[0x6002, 0xF018], Quirks::default());
+        assert!(machine.is_beeping());
+
+        machine.run_frame(0, |_, _| false).unwrap();
+        assert!(machine.is_beeping());
+        machine.run_frame(0, |_, _| false).unwrap();
+        assert!(!machine.is_beeping());
+    }
+
+    #[test]
+    fn read_only_rom_ignores_writes_into_the_rom_only() {
+        // V0 = 0x55, store it at 0x200 and at 0x300
+        let quirks = Quirks {
+            rom_readonly: true,
+            ..Quirks::default()
+        };
+        let machine = run_program(&[0x6055, 0xA200, 0xF055, 0xA300, 0xF055], quirks);
+
+        assert_eq!(machine.memory[0x200], 0x60);
+        assert_eq!(machine.memory[0x300], 0x55);
+    }
+
+    #[test]
+    fn one_frame_executes_cycles_opcodes_and_ticks_the_timers_once() {
+        // Both timers = 5, then keep incrementing V1
+        let mut machine = run_program(&[0x6005, 0xF015, 0xF018, 0x7101, 0x1206], Quirks::default());
+        let executed = machine.instruction_count;
+
+        machine.run_frame(6, |_, _| false).unwrap();
+        assert_eq!(machine.instruction_count - executed, 6);
+        assert_eq!(machine.registers[1], 4);
+        assert_eq!(machine.delay_timer, 4);
+        assert_eq!(machine.sound_timer, 4);
+    }
+
+    #[test]
+    fn frame_seeded_random_numbers_only_depend_on_the_frame() {
+        let run = |rng_seed| {
+            // V0 and V1 random, every frame
+            let mut machine = VirtualMachine::from_opcodes(&[0xC0FF, 0xC1FF, 0x1200]);
+            machine.seed_rng(rng_seed);
+            machine.frame_rng_seed = Some(9);
+            (0..8)
+                .map(|_| {
+                    machine.run_frame(3, |_, _| false).unwrap();
+                    (machine.registers[0], machine.registers[1])
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let first = run(1);
+        assert_eq!(run(2), first);
+        assert_ne!(first[0], first[1]);
+    }
+
+    #[test]
+    fn returning_to_an_empty_stack_halts_with_the_flag() {
+        // Call a subroutine that returns right away, then loop
+        let program = [0x2204, 0x1202, 0x00EE];
+        for halt_on_top_return in [false, true] {
+            let quirks = Quirks {
+                halt_on_top_return,
+                ..Quirks::default()
+            };
+            let machine = run_program(&program, quirks);
+
+            assert_eq!(machine.pc, 0x202);
+            assert_eq!(machine.is_halted(), halt_on_top_return);
+        }
+    }
+
+    #[test]
+    fn wait_profile_measures_instructions_until_the_delay_timer_expires() {
+        // Delay timer = 3, then idle
+        let mut machine = VirtualMachine::from_opcodes(&[0x6003, 0xF015, 0x1204]);
+        machine.profile_waits = true;
+        for _ in 0..3 {
+            machine.run_frame(4, |_, _| false).unwrap();
+        }
+        assert_eq!(machine.average_wait(), None);
+
+        // FX15 was the 2nd instruction and the timer expires at the start
+        // of the 4th frame, after 12
+        machine.run_frame(4, |_, _| false).unwrap();
+        assert_eq!(machine.delay_timer, 0);
+        assert_eq!(machine.average_wait(), Some((10.0, 1)));
+    }
+
+    #[test]
+    fn wait_for_key_completes_once_on_release() {
+        // V3 = key, then idle
+        let mut machine = VirtualMachine::from_opcodes(&[0xF30A, 0x1202]);
+        machine.step().unwrap();
+        assert_eq!(machine.pc, 0x200);
+
+        machine.pressed_key = Some(5);
+        for _ in 0..3 {
+            machine.step().unwrap();
+            assert_eq!(machine.pc, 0x200);
+            assert_eq!(machine.registers[3], 0);
+        }
+
+        machine.pressed_key = None;
+        machine.step().unwrap();
+        assert_eq!(machine.pc, 0x202);
+        assert_eq!(machine.registers[3], 5);
+
+        // Pressing another key afterwards changes nothing
+        machine.registers[3] = 0;
+        machine.pressed_key = Some(7);
+        for _ in 0..3 {
+            machine.step().unwrap();
+        }
+        assert_eq!(machine.registers[3], 0);
+        assert_eq!(machine.pending_key, None);
+    }
+
+    #[test]
+    fn render_to_buffer_scales_and_colors_pixels() {
+        let mut machine = VirtualMachine::from_opcodes(&[0x00E0]);
+        machine.canvas[0].set_pixel(2, 1, true);
+        let (fg, bg) = ((1, 2, 3), (4, 5, 6));
+        let pixels = machine.render_to_buffer(3, fg, bg);
+        assert_eq!(pixels.len(), WIDTH * 3 * HEIGHT * 3 * 4);
+
+        let host_pixel = |x: usize, y: usize| {
+            let offset = (y * WIDTH * 3 + x) * 4;
+            &pixels[offset..offset + 4]
+        };
+        // CHIP-8 pixel (2, 1) covers host pixels 6..9 by 3..6
+        assert_eq!(host_pixel(6, 3), [1, 2, 3, 0xFF]);
+        assert_eq!(host_pixel(8, 5), [1, 2, 3, 0xFF]);
+        assert_eq!(host_pixel(5, 3), [4, 5, 6, 0xFF]);
+        assert_eq!(host_pixel(9, 5), [4, 5, 6, 0xFF]);
+        assert_eq!(host_pixel(6, 6), [4, 5, 6, 0xFF]);
     }
 }
@@ -0,0 +1,84 @@
+use anyhow::{bail, Result};
+
+use crate::virtual_machine::VirtualMachine;
+
+#[derive(Debug, Clone, Copy)]
+enum Location {
+    Register(u8),
+    Memory(u16),
+}
+
+impl Location {
+    /// Accepts `VX` for a register or `mem:ADDR` (hexadecimal) for a memory byte
+    fn parse(text: &str) -> Result<Self> {
+        if let Some(address) = text.strip_prefix("mem:") {
+            let address = crate::parse_hex(address, "watch address")?;
+            if address >= 0x1000 {
+                bail!("Watch address out of bounds: {:#X}", address);
+            }
+            return Ok(Location::Memory(address));
+        }
+
+        if let Some(register) = text.strip_prefix(['V', 'v']) {
+            if register.len() == 1 {
+                if let Ok(register) = u8::from_str_radix(register, 16) {
+                    return Ok(Location::Register(register));
+                }
+            }
+        }
+
+        bail!("Invalid watch: {} (expected VX or mem:ADDR)", text)
+    }
+
+    fn read(self, machine: &VirtualMachine) -> u8 {
+        match self {
+            Location::Register(register) => machine.registers()[register as usize],
+            // Addresses are validated when parsing
+            Location::Memory(address) => machine.read_memory(address).unwrap_or_default(),
+        }
+    }
+}
+
+/// Watched registers and memory bytes together with their last seen values
+pub struct Watchpoints {
+    watches: Vec<(Location, u8)>,
+}
+
+impl Watchpoints {
+    pub fn new(specs: &[String], machine: &VirtualMachine) -> Result<Self> {
+        let watches = specs
+            .iter()
+            .map(|spec| {
+                let location = Location::parse(spec)?;
+                Ok((location, location.read(machine)))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self { watches })
+    }
+
+    /// Reports every watched location that changed since the last check.
+    /// `pc` is the address of the opcode that was just executed.
+    /// Returns true if anything changed.
+    pub fn check(&mut self, machine: &VirtualMachine, pc: u16) -> bool {
+        let mut changed = false;
+
+        for (location, last_value) in &mut self.watches {
+            let value = location.read(machine);
+            if value != *last_value {
+                let name = match location {
+                    Location::Register(register) => format!("V{:X}", register),
+                    Location::Memory(address) => format!("mem:{:#05X}", address),
+                };
+                eprintln!(
+                    "Watch {} changed {:#04X} -> {:#04X} (pc {:#05X})",
+                    name, last_value, value, pc
+                );
+                *last_value = value;
+                changed = true;
+            }
+        }
+
+        changed
+    }
+}